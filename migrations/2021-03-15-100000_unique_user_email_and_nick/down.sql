@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX users_nick_unique_idx;
+DROP INDEX users_email_unique_idx;
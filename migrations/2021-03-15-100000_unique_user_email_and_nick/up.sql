@@ -0,0 +1,43 @@
+-- normalize what the api now normalizes on input
+UPDATE users SET email = lower(trim(email)), nick = trim(nick);
+
+-- the same email can not be assigned to two accounts automatically, resolve by hand
+DO $$
+BEGIN
+    IF EXISTS (SELECT email FROM users GROUP BY email HAVING count(*) > 1) THEN
+        RAISE EXCEPTION 'users contains duplicate emails, merge these accounts before migrating';
+    END IF;
+END
+$$;
+
+-- keep the oldest nick, disambiguate later duplicates with their id. The nick is shortened to
+-- make room for the suffix, a counter is added while the result is taken by another user.
+DO $$
+DECLARE
+    duplicate RECORD;
+    suffix text;
+    candidate text;
+    attempt integer;
+BEGIN
+    FOR duplicate IN
+        SELECT id, nick FROM users
+        WHERE id NOT IN (SELECT min(id) FROM users GROUP BY lower(nick))
+        ORDER BY id
+    LOOP
+        attempt := 0;
+        LOOP
+            suffix := '-' || duplicate.id;
+            IF attempt > 0 THEN
+                suffix := suffix || '-' || attempt;
+            END IF;
+            candidate := left(duplicate.nick, 256 - length(suffix)) || suffix;
+            EXIT WHEN NOT EXISTS (SELECT 1 FROM users WHERE lower(nick) = lower(candidate));
+            attempt := attempt + 1;
+        END LOOP;
+        UPDATE users SET nick = candidate WHERE id = duplicate.id;
+    END LOOP;
+END
+$$;
+
+CREATE UNIQUE INDEX users_email_unique_idx ON users (lower(email));
+CREATE UNIQUE INDEX users_nick_unique_idx ON users (lower(nick));
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX items_list_id_idx;
+DROP INDEX lists_user_id_idx;
+ALTER TABLE items DROP CONSTRAINT items_list_id_fkey;
+ALTER TABLE lists DROP CONSTRAINT lists_user_id_fkey;
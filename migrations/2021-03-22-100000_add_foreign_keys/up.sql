@@ -0,0 +1,11 @@
+-- remove orphans first, lists before items so items of removed lists go as well
+DELETE FROM lists WHERE user_id NOT IN (SELECT id FROM users);
+DELETE FROM items WHERE list_id NOT IN (SELECT id FROM lists);
+
+ALTER TABLE lists
+    ADD CONSTRAINT lists_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE items
+    ADD CONSTRAINT items_list_id_fkey FOREIGN KEY (list_id) REFERENCES lists (id) ON DELETE CASCADE;
+
+CREATE INDEX lists_user_id_idx ON lists (user_id);
+CREATE INDEX items_list_id_idx ON items (list_id);
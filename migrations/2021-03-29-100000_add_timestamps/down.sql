@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX lists_updated_at_idx;
+DROP TRIGGER touch_list_updated_at ON items;
+DROP FUNCTION touch_list_updated_at();
+
+DROP TRIGGER set_updated_at ON items;
+DROP TRIGGER set_updated_at ON lists;
+DROP TRIGGER set_updated_at ON users;
+
+ALTER TABLE items DROP COLUMN created_at, DROP COLUMN updated_at;
+ALTER TABLE lists DROP COLUMN created_at, DROP COLUMN updated_at;
+ALTER TABLE users DROP COLUMN created_at, DROP COLUMN updated_at;
@@ -0,0 +1,30 @@
+ALTER TABLE users
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE lists
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE items
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+SELECT diesel_manage_updated_at('users');
+SELECT diesel_manage_updated_at('lists');
+SELECT diesel_manage_updated_at('items');
+
+-- changing an item counts as changing its list
+CREATE OR REPLACE FUNCTION touch_list_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        UPDATE lists SET updated_at = current_timestamp WHERE id = OLD.list_id;
+        RETURN OLD;
+    END IF;
+    UPDATE lists SET updated_at = current_timestamp WHERE id = NEW.list_id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER touch_list_updated_at AFTER INSERT OR UPDATE OR DELETE ON items
+    FOR EACH ROW EXECUTE PROCEDURE touch_list_updated_at();
+
+CREATE INDEX lists_updated_at_idx ON lists (updated_at);
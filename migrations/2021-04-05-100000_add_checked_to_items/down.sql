@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE items DROP COLUMN checked, DROP COLUMN checked_at, DROP COLUMN checked_by;
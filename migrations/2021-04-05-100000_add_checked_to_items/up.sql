@@ -0,0 +1,4 @@
+ALTER TABLE items
+    ADD COLUMN checked BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN checked_at TIMESTAMPTZ,
+    ADD COLUMN checked_by BIGINT REFERENCES users (id) ON DELETE SET NULL;
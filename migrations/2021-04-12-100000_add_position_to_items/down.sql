@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX items_list_id_position_idx;
+ALTER TABLE items DROP COLUMN position;
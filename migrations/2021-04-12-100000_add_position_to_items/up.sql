@@ -0,0 +1,10 @@
+-- positions are spaced out so an item can be inserted between two others without renumbering
+ALTER TABLE items ADD COLUMN position BIGINT NOT NULL DEFAULT 0;
+
+UPDATE items SET position = numbered.row_number * 1024
+FROM (
+    SELECT id, row_number() OVER (PARTITION BY list_id ORDER BY id) AS row_number FROM items
+) AS numbered
+WHERE items.id = numbered.id;
+
+CREATE INDEX items_list_id_position_idx ON items (list_id, position);
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE items ADD COLUMN amount INTEGER NOT NULL DEFAULT 0;
+
+-- fractional quantities are rounded, units are lost
+UPDATE items SET amount = round(quantity)::INTEGER;
+
+ALTER TABLE items
+    ALTER COLUMN amount DROP DEFAULT,
+    DROP COLUMN quantity,
+    DROP COLUMN unit;
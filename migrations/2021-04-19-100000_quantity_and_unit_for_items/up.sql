@@ -0,0 +1,11 @@
+ALTER TABLE items
+    ADD COLUMN quantity NUMERIC(12, 3) NOT NULL DEFAULT 1,
+    ADD COLUMN unit varchar(16) NOT NULL DEFAULT 'pcs';
+
+-- existing amounts were plain counts
+UPDATE items SET quantity = amount, unit = 'pcs';
+
+ALTER TABLE items
+    DROP COLUMN amount,
+    ADD CONSTRAINT items_quantity_not_negative CHECK (quantity >= 0),
+    ADD CONSTRAINT items_unit_known CHECK (unit IN ('pcs', 'g', 'kg', 'ml', 'l', 'packs', 'bottles', 'cans'));
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX items_search_vector_idx;
+DROP INDEX lists_search_vector_idx;
+
+DROP TRIGGER items_search_vector_update ON items;
+DROP TRIGGER lists_search_vector_update ON lists;
+DROP FUNCTION items_search_vector_update();
+DROP FUNCTION lists_search_vector_update();
+
+ALTER TABLE items DROP COLUMN search_vector;
+ALTER TABLE lists DROP COLUMN search_vector;
+
+DROP FUNCTION retrolist_search_language();
@@ -0,0 +1,48 @@
+-- The text search configuration is taken from the session setting retrolist.search_language,
+-- which the api sets on every pooled connection from SEARCH_LANGUAGE. Falls back to english.
+CREATE OR REPLACE FUNCTION retrolist_search_language() RETURNS regconfig AS $$
+BEGIN
+    RETURN coalesce(nullif(current_setting('retrolist.search_language', true), ''), 'english')::regconfig;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+ALTER TABLE lists ADD COLUMN search_vector tsvector;
+ALTER TABLE items ADD COLUMN search_vector tsvector;
+
+CREATE OR REPLACE FUNCTION lists_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector :=
+        setweight(to_tsvector(retrolist_search_language(), coalesce(NEW.title, '')), 'A') ||
+        setweight(to_tsvector(retrolist_search_language(), coalesce(NEW.subtitle, '')), 'B');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION items_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector := to_tsvector(retrolist_search_language(), coalesce(NEW.title, ''));
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER lists_search_vector_update BEFORE INSERT OR UPDATE OF title, subtitle ON lists
+    FOR EACH ROW EXECUTE PROCEDURE lists_search_vector_update();
+CREATE TRIGGER items_search_vector_update BEFORE INSERT OR UPDATE OF title ON items
+    FOR EACH ROW EXECUTE PROCEDURE items_search_vector_update();
+
+-- backfill without bumping updated_at of every row
+ALTER TABLE lists DISABLE TRIGGER set_updated_at;
+ALTER TABLE items DISABLE TRIGGER set_updated_at;
+ALTER TABLE items DISABLE TRIGGER touch_list_updated_at;
+
+UPDATE lists SET search_vector =
+    setweight(to_tsvector(retrolist_search_language(), title), 'A') ||
+    setweight(to_tsvector(retrolist_search_language(), subtitle), 'B');
+UPDATE items SET search_vector = to_tsvector(retrolist_search_language(), title);
+
+ALTER TABLE items ENABLE TRIGGER touch_list_updated_at;
+ALTER TABLE items ENABLE TRIGGER set_updated_at;
+ALTER TABLE lists ENABLE TRIGGER set_updated_at;
+
+CREATE INDEX lists_search_vector_idx ON lists USING GIN (search_vector);
+CREATE INDEX items_search_vector_idx ON items USING GIN (search_vector);
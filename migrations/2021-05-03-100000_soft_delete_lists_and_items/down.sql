@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+-- rows in the trash are gone for good when migrating down
+DELETE FROM items WHERE deleted_at IS NOT NULL;
+DELETE FROM lists WHERE deleted_at IS NOT NULL;
+
+DROP INDEX items_deleted_at_idx;
+DROP INDEX lists_deleted_at_idx;
+
+ALTER TABLE items DROP COLUMN deleted_at;
+ALTER TABLE lists DROP COLUMN deleted_at;
@@ -0,0 +1,6 @@
+ALTER TABLE lists ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE items ADD COLUMN deleted_at TIMESTAMPTZ;
+
+-- the trash and the retention job only look at deleted rows
+CREATE INDEX lists_deleted_at_idx ON lists (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX items_deleted_at_idx ON items (deleted_at) WHERE deleted_at IS NOT NULL;
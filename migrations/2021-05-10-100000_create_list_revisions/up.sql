@@ -0,0 +1,15 @@
+-- every change of a list or its items is recorded with the state it produced,
+-- numbered per list so a revision can be addressed as /list/:id/revert/:revision
+CREATE TABLE list_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    list_id BIGINT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    revision BIGINT NOT NULL,
+    user_id BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    action VARCHAR NOT NULL,
+    diff JSONB NOT NULL,
+    snapshot JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT list_revisions_list_id_revision_key UNIQUE (list_id, revision)
+);
+
+CREATE INDEX list_revisions_user_id_idx ON list_revisions (user_id);
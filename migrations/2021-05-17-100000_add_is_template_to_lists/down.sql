@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX lists_user_id_templates_idx;
+
+ALTER TABLE lists DROP COLUMN is_template;
@@ -0,0 +1,4 @@
+-- templates are regular lists that are kept out of the normal list views
+ALTER TABLE lists ADD COLUMN is_template BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX lists_user_id_templates_idx ON lists (user_id) WHERE is_template;
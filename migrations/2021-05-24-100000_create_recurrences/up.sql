@@ -0,0 +1,17 @@
+-- a template with a recurrence gets a fresh list at every occurrence,
+-- next_run_at is advanced in the same transaction that creates the list
+CREATE TABLE recurrences (
+    id BIGSERIAL PRIMARY KEY,
+    template_id BIGINT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    rule VARCHAR NOT NULL,
+    starts_at TIMESTAMPTZ NOT NULL,
+    next_run_at TIMESTAMPTZ,
+    last_run_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT recurrences_template_id_key UNIQUE (template_id)
+);
+
+CREATE INDEX recurrences_next_run_at_idx ON recurrences (next_run_at);
+
+SELECT diesel_manage_updated_at('recurrences');
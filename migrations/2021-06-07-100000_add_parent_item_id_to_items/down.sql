@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX items_parent_item_id_idx;
+
+ALTER TABLE items DROP CONSTRAINT items_parent_item_id_not_self;
+
+ALTER TABLE items DROP CONSTRAINT items_parent_item_id_fkey;
+
+ALTER TABLE items DROP COLUMN parent_item_id;
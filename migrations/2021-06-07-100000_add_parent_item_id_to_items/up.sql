@@ -0,0 +1,10 @@
+-- items can be nested under another item of the same list, sub-items go with their parent.
+-- The key is checked at commit so a list can be restored in any item order.
+ALTER TABLE items ADD COLUMN parent_item_id BIGINT;
+
+ALTER TABLE items ADD CONSTRAINT items_parent_item_id_fkey FOREIGN KEY (parent_item_id)
+    REFERENCES items (id) ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED;
+
+ALTER TABLE items ADD CONSTRAINT items_parent_item_id_not_self CHECK (parent_item_id <> id);
+
+CREATE INDEX items_parent_item_id_idx ON items (parent_item_id);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX items_pending_reminders_idx;
+DROP INDEX items_due_at_idx;
+
+ALTER TABLE items DROP COLUMN reminded_at;
+ALTER TABLE items DROP COLUMN remind_at;
+ALTER TABLE items DROP COLUMN due_at;
@@ -0,0 +1,9 @@
+-- optional due date and reminder time of items, reminded_at is set once the reminder went out
+ALTER TABLE items ADD COLUMN due_at TIMESTAMPTZ;
+ALTER TABLE items ADD COLUMN remind_at TIMESTAMPTZ;
+ALTER TABLE items ADD COLUMN reminded_at TIMESTAMPTZ;
+
+CREATE INDEX items_due_at_idx ON items (due_at) WHERE due_at IS NOT NULL AND deleted_at IS NULL;
+
+CREATE INDEX items_pending_reminders_idx ON items (remind_at)
+    WHERE remind_at IS NOT NULL AND reminded_at IS NULL AND deleted_at IS NULL;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE items DROP CONSTRAINT items_unit_price_not_negative;
+ALTER TABLE items DROP CONSTRAINT items_unit_price_with_currency;
+
+ALTER TABLE items DROP COLUMN currency;
+ALTER TABLE items DROP COLUMN unit_price;
+ALTER TABLE items DROP COLUMN url;
+ALTER TABLE items DROP COLUMN notes;
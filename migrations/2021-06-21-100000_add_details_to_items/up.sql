@@ -0,0 +1,10 @@
+-- markdown notes, a link and the price of one unit of an item, prices always come with their currency
+ALTER TABLE items ADD COLUMN notes TEXT;
+ALTER TABLE items ADD COLUMN url VARCHAR(2048);
+ALTER TABLE items ADD COLUMN unit_price NUMERIC(12, 2);
+ALTER TABLE items ADD COLUMN currency VARCHAR(3);
+
+ALTER TABLE items ADD CONSTRAINT items_unit_price_with_currency
+    CHECK ((unit_price IS NULL) = (currency IS NULL));
+
+ALTER TABLE items ADD CONSTRAINT items_unit_price_not_negative CHECK (unit_price >= 0);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE notifications;
+
+ALTER TABLE lists DROP CONSTRAINT lists_budget_amount_positive;
+ALTER TABLE lists DROP CONSTRAINT lists_budget_with_currency;
+
+ALTER TABLE lists DROP COLUMN budget_notified_percent;
+ALTER TABLE lists DROP COLUMN budget_currency;
+ALTER TABLE lists DROP COLUMN budget_amount;
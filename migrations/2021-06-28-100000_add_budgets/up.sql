@@ -0,0 +1,21 @@
+-- optional budget of a list, budget_notified_percent is the highest threshold the owner was told about
+ALTER TABLE lists ADD COLUMN budget_amount NUMERIC(12, 2);
+ALTER TABLE lists ADD COLUMN budget_currency VARCHAR(3);
+ALTER TABLE lists ADD COLUMN budget_notified_percent SMALLINT;
+
+ALTER TABLE lists ADD CONSTRAINT lists_budget_with_currency
+    CHECK ((budget_amount IS NULL) = (budget_currency IS NULL));
+
+ALTER TABLE lists ADD CONSTRAINT lists_budget_amount_positive CHECK (budget_amount > 0);
+
+-- notifications raised inside a transaction wait here until they are delivered
+CREATE TABLE notifications (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    claimed_at TIMESTAMPTZ,
+    CONSTRAINT notifications_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
@@ -0,0 +1,5 @@
+-- Search snippets are HTML, user text is escaped before the matches are wrapped in <mark></mark>
+CREATE OR REPLACE FUNCTION retrolist_escape_html(text) RETURNS text AS $$
+    SELECT replace(replace(replace(replace(replace($1,
+        '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;');
+$$ LANGUAGE sql IMMUTABLE STRICT;
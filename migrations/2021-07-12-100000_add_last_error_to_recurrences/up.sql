@@ -0,0 +1,2 @@
+-- why the list of the last occurrence could not be created, cleared once an occurrence succeeds
+ALTER TABLE recurrences ADD COLUMN last_error VARCHAR;
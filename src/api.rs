@@ -1,22 +1,41 @@
-use crate::db;
-use crate::errors::ApiError;
-use crate::models::{CreateItem, CreateList, Item, List};
+use crate::auth::{Auth, Claims, Scope};
+use crate::db::{self, AddedItem};
+use crate::errors::{ApiError, ErrorType};
+use crate::export;
+use crate::matching::{DuplicatePolicy, OnDuplicate};
+use crate::models::{
+    max_quantity, normalize_email, normalize_nick, BudgetStatus, CreateItem, CreateList,
+    CreateRecurrence, CreateTag, Item, ItemDetails, ItemNode, List, ListFilter, ListSort, Progress,
+    SortOrder, Totals, Unit,
+};
+use crate::notify::Notification;
+use crate::pagination::{self, Cursor, Page};
+use crate::recurrence::RecurrenceRule;
+use crate::webauthn::actors::WebauthnActor;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 // Api List Wrapper Struct
 #[derive(Debug, Deserialize, Clone)]
 pub struct AddList {
-    pub user_id: i64,
     pub title: String,
     pub subtitle: String,
+    // only used on creation, a list does not turn into a template by updating it
+    #[serde(default)]
+    pub is_template: bool,
 }
 
 impl AddList {
-    pub fn to_dto(&self) -> CreateList {
+    pub fn to_dto(&self, user_id: i64) -> CreateList {
         CreateList {
-            user_id: self.user_id.clone(),
+            user_id,
             title: self.title.clone(),
             subtitle: self.subtitle.clone(),
+            is_template: self.is_template,
+            budget_amount: None,
+            budget_currency: None,
         }
     }
 }
@@ -26,23 +45,479 @@ impl AddList {
 pub struct AddItem {
     pub list_id: i64,
     pub title: String,
-    pub amount: i32,
+    pub quantity: BigDecimal,
+    #[serde(default)]
+    pub unit: Unit,
+    // index to insert the item at, appended to the list if missing
+    pub position: Option<usize>,
+    // item of the same list to nest the new one under
+    pub parent_item_id: Option<i64>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub details: SetDetails,
+    #[serde(default)]
+    pub on_duplicate: OnDuplicate,
+    // how alike titles have to be to count as duplicates, between 0 and 1, same titles only if missing
+    pub similarity: Option<f64>,
 }
 
 impl AddItem {
-    pub fn to_dto(&self) -> CreateItem {
-        CreateItem {
+    pub fn to_dto(&self) -> Result<CreateItem, ApiError> {
+        Ok(CreateItem {
             list_id: self.list_id.clone(),
-            title: self.title.clone(),
-            amount: self.amount.clone(),
+            title: self.title.trim().to_string(),
+            quantity: self.quantity.clone(),
+            unit: self.unit,
+            parent_item_id: self.parent_item_id,
+            due_at: self.due_at,
+            remind_at: self.remind_at,
+            details: self.details.validated()?,
+        })
+    }
+}
+
+/// quantities must be positive and fit into NUMERIC(12, 3)
+fn validate_quantity(quantity: &BigDecimal) -> Result<(), ApiError> {
+    if *quantity <= BigDecimal::from(0) {
+        return Err(ApiError::new(
+            "quantity: must be greater than 0",
+            ErrorType::BadRequest,
+        ));
+    }
+    if *quantity >= max_quantity() {
+        return Err(ApiError::new(
+            "quantity: must be less than 1000000000",
+            ErrorType::BadRequest,
+        ));
+    }
+    Ok(())
+}
+
+/// longest accepted notes, in characters
+const MAX_NOTES_LENGTH: usize = 10_000;
+
+/// longest accepted link, the size of the column
+const MAX_URL_LENGTH: usize = 2048;
+
+/// largest amount of money that fits into NUMERIC(12, 2), exclusive
+fn max_amount() -> BigDecimal {
+    BigDecimal::from(10_000_000_000i64)
+}
+
+/// trimmed and upper case three letter ISO 4217 code
+fn validate_currency(currency: &str) -> Result<String, ApiError> {
+    let currency = currency.trim().to_uppercase();
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(ApiError::new(
+            "currency: must be a three letter ISO 4217 code",
+            ErrorType::BadRequest,
+        ));
+    }
+    Ok(currency)
+}
+
+// Notes, link and price of an item, also the body of PUT /item/:id/details which replaces all of them
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SetDetails {
+    // markdown
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    // price of one of the item's unit
+    pub unit_price: Option<BigDecimal>,
+    // ISO 4217 code like EUR, required with a price
+    pub currency: Option<String>,
+}
+
+impl SetDetails {
+    /// trimmed text, blank text dropped and the currency in upper case
+    pub fn validated(&self) -> Result<ItemDetails, ApiError> {
+        let invalid = |message: &str| ApiError::new(message, ErrorType::BadRequest);
+        let trimmed = |text: &Option<String>| {
+            text.as_ref()
+                .map(|text| text.trim().to_string())
+                .filter(|text| !text.is_empty())
+        };
+
+        let notes = trimmed(&self.notes);
+        if notes
+            .as_ref()
+            .is_some_and(|notes| notes.chars().count() > MAX_NOTES_LENGTH)
+        {
+            return Err(invalid(
+                format!("notes: must be at most {} characters", MAX_NOTES_LENGTH).as_str(),
+            ));
+        }
+
+        let url = trimmed(&self.url);
+        if let Some(url) = &url {
+            let rest = url
+                .strip_prefix("https://")
+                .or_else(|| url.strip_prefix("http://"))
+                .unwrap_or_default();
+            if rest.is_empty()
+                || url.len() > MAX_URL_LENGTH
+                || url.chars().any(|c| c.is_whitespace() || c.is_control())
+            {
+                return Err(invalid(
+                    format!(
+                        "url: must be an http or https URL of at most {} characters",
+                        MAX_URL_LENGTH
+                    )
+                    .as_str(),
+                ));
+            }
+        }
+
+        let currency = match &self.currency {
+            Some(currency) if !currency.trim().is_empty() => Some(validate_currency(currency)?),
+            _ => None,
+        };
+        if let Some(price) = &self.unit_price {
+            if *price < BigDecimal::from(0) || *price >= max_amount() {
+                return Err(invalid(
+                    "unit_price: must be at least 0 and less than 10000000000",
+                ));
+            }
+        }
+        if self.unit_price.is_some() != currency.is_some() {
+            return Err(invalid(
+                "currency: must be given together with a unit_price",
+            ));
         }
+
+        Ok(ItemDetails {
+            notes,
+            url,
+            unit_price: self.unit_price.clone(),
+            currency,
+        })
     }
 }
 
+/// operations accepted in one batch, the body size limit allows about this many
+const MAX_BATCH_OPERATIONS: usize = 100;
+
+// One operation of POST /list/:id/items:batch
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Create {
+        title: String,
+        quantity: BigDecimal,
+        #[serde(default)]
+        unit: Unit,
+        position: Option<usize>,
+        parent_item_id: Option<i64>,
+        due_at: Option<DateTime<Utc>>,
+        remind_at: Option<DateTime<Utc>>,
+        #[serde(flatten)]
+        details: SetDetails,
+        #[serde(default)]
+        on_duplicate: OnDuplicate,
+        similarity: Option<f64>,
+    },
+    Update {
+        id: i64,
+        title: String,
+        quantity: BigDecimal,
+        // keeps the stored unit if missing
+        unit: Option<Unit>,
+    },
+    Delete {
+        id: i64,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BatchItems {
+    pub operations: Vec<BatchOperation>,
+}
+
+// Outcome of one batch operation, with the status the single item endpoint would have answered
+#[derive(Debug, Serialize, Clone)]
+pub struct BatchResult {
+    pub op: &'static str,
+    pub id: i64,
+    pub status: u16,
+}
+
+// Api Body Struct for POST /item/:id/move and /item/:id/copy
+#[derive(Debug, Deserialize, Clone)]
+pub struct TransferItem {
+    pub list_id: i64,
+    // index in the target list, appended if missing
+    pub position: Option<usize>,
+}
+
+// Api Body Struct for POST /items:move and /items:copy
+#[derive(Debug, Deserialize, Clone)]
+pub struct TransferItems {
+    pub item_ids: Vec<i64>,
+    pub list_id: i64,
+    pub position: Option<usize>,
+}
+
+// Api Body Struct for PUT /item/:id/parent, no parent moves the item to the top level
+#[derive(Debug, Deserialize, Clone)]
+pub struct SetParent {
+    pub parent_item_id: Option<i64>,
+}
+
+// Api Body Struct for PUT /item/:id/schedule, missing values clear the due date or reminder
+#[derive(Debug, Deserialize, Clone)]
+pub struct SetSchedule {
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ToggleQuery {
+    // give the sub-items the new checked state too
+    pub cascade: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReorderItems {
+    pub item_ids: Vec<i64>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct UpdateItem {
     pub title: String,
-    pub amount: i32,
+    pub quantity: BigDecimal,
+    // keeps the stored unit if missing, clients from before units only send the quantity
+    pub unit: Option<Unit>,
+}
+
+// What to embed in list collection responses, summary (progress counts) is the default
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListInclude {
+    Items,
+    Summary,
+}
+
+impl ListInclude {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListInclude::Items => "items",
+            ListInclude::Summary => "summary",
+        }
+    }
+}
+
+/// What kind of entity to take out of the trash, matched from the path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrashKind {
+    List,
+    Item,
+}
+
+impl std::str::FromStr for TrashKind {
+    type Err = ApiError;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "list" => Ok(TrashKind::List),
+            "item" => Ok(TrashKind::Item),
+            _ => Err(ApiError::new(
+                "kind: must be list or item",
+                ErrorType::BadRequest,
+            )),
+        }
+    }
+}
+
+// Api Body Struct for PUT /list/:id/budget
+#[derive(Debug, Deserialize, Clone)]
+pub struct SetBudget {
+    pub amount: BigDecimal,
+    pub currency: String,
+}
+
+impl SetBudget {
+    pub fn validated(&self) -> Result<(BigDecimal, String), ApiError> {
+        if self.amount <= BigDecimal::from(0) || self.amount >= max_amount() {
+            return Err(ApiError::new(
+                "amount: must be greater than 0 and less than 10000000000",
+                ErrorType::BadRequest,
+            ));
+        }
+        Ok((self.amount.clone(), validate_currency(&self.currency)?))
+    }
+}
+
+// Api Body Struct for POST /tags and PUT /tag/:id
+#[derive(Debug, Deserialize, Clone)]
+pub struct TagName {
+    pub name: String,
+}
+
+impl TagName {
+    /// trimmed name, it has to fit the column
+    pub fn validated(&self) -> Result<String, ApiError> {
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Err(ApiError::new(
+                "name: must be between 1 and 64 characters",
+                ErrorType::BadRequest,
+            ));
+        }
+        Ok(name.to_string())
+    }
+}
+
+// Api Body Struct for POST /list/:id/clone
+#[derive(Debug, Deserialize, Clone)]
+pub struct CloneList {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub reset_checked: bool,
+    #[serde(default)]
+    pub as_template: bool,
+}
+
+// Api Body Struct for POST /template/:id/instantiate
+#[derive(Debug, Deserialize, Clone)]
+pub struct InstantiateTemplate {
+    pub title: Option<String>,
+}
+
+// A recurrence rule, either as RRULE string or spelled out
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RuleSpec {
+    Rrule(String),
+    Rule(RecurrenceRule),
+}
+
+// Api Body Struct for PUT /template/:id/recurrence
+#[derive(Debug, Deserialize, Clone)]
+pub struct SetRecurrence {
+    pub rule: RuleSpec,
+    // first possible occurrence and time of day of all of them, defaults to now
+    pub starts_at: Option<DateTime<Utc>>,
+}
+
+impl SetRecurrence {
+    pub fn to_dto(&self, template_id: i64) -> Result<CreateRecurrence, ApiError> {
+        let rule = match &self.rule {
+            RuleSpec::Rrule(rrule) => rrule.parse::<RecurrenceRule>()?,
+            RuleSpec::Rule(rule) => {
+                rule.validate()?;
+                rule.clone()
+            }
+        };
+        let now = Utc::now();
+        let starts_at = self.starts_at.unwrap_or(now);
+
+        match rule.first_after(starts_at, now) {
+            Some(next_run_at) => Ok(CreateRecurrence {
+                template_id,
+                rule: rule.to_string(),
+                starts_at,
+                next_run_at: Some(next_run_at),
+            }),
+            None => Err(ApiError::new(
+                "rule: never occurs",
+                ErrorType::UnprocessableEntity,
+            )),
+        }
+    }
+}
+
+// Api Query Struct for GET /list/:id/history
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistoryQuery {
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+// Api Query Struct for GET /lists
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListsQuery {
+    pub include: Option<ListInclude>,
+    pub sort: Option<ListSort>,
+    pub order: Option<SortOrder>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub title: Option<String>,
+    pub has_unchecked: Option<bool>,
+    pub tag: Option<String>,
+}
+
+impl ListsQuery {
+    /// the filter for the lists of the query, only lists of the caller unless they are an admin,
+    /// tags are the ones of the caller
+    pub fn to_filter(&self, caller: &Claims) -> ListFilter {
+        ListFilter {
+            owner_id: if caller.has_scope(Scope::Admin) {
+                None
+            } else {
+                Some(caller.sub)
+            },
+            title_contains: self.title.clone(),
+            has_unchecked: self.has_unchecked,
+            tag: self.tag.clone(),
+            tag_owner_id: Some(caller.sub),
+        }
+    }
+
+    /// link to the page starting at the given cursor, keeping all other parameters
+    pub fn next_link(&self, sort: ListSort, order: SortOrder, limit: i64, cursor: &str) -> String {
+        let mut link = format!(
+            "/api/lists?sort={}&order={}&limit={}&cursor={}",
+            sort.as_str(),
+            order.as_str(),
+            limit,
+            cursor
+        );
+        if let Some(title) = &self.title {
+            link.push_str(format!("&title={}", pagination::encode_query_value(title)).as_str());
+        }
+        if let Some(tag) = &self.tag {
+            link.push_str(format!("&tag={}", pagination::encode_query_value(tag)).as_str());
+        }
+        if let Some(has_unchecked) = self.has_unchecked {
+            link.push_str(format!("&has_unchecked={}", has_unchecked).as_str());
+        }
+        if let Some(include) = self.include {
+            link.push_str(format!("&include={}", include.as_str()).as_str());
+        }
+        link
+    }
+}
+
+// Api Query Struct for GET /search
+#[derive(Debug, Deserialize, Clone)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DueQuery {
+    // defaults to now, which lists the overdue items
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+// Api Response Struct for GET /due
+#[derive(Debug, Serialize, Clone)]
+pub struct DueItem {
+    #[serde(flatten)]
+    pub item: Item,
+    pub list_title: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ListWithProgress {
+    #[serde(flatten)]
+    pub list: List,
+    pub progress: Progress,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<Item>>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -51,21 +526,82 @@ pub struct ListWithItems {
     pub user_id: i64,
     pub title: String,
     pub subtitle: String,
-    pub items: Vec<Item>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub progress: Progress,
+    pub totals: Totals,
+    // None if the list has no budget
+    pub budget: Option<BudgetStatus>,
+    // top level items, with their sub-items nested
+    pub items: Vec<ItemNode>,
 }
 
 impl ListWithItems {
     pub fn new(list: List, items: Vec<Item>) -> ListWithItems {
+        let totals = Totals::of_items(&items);
         ListWithItems {
+            progress: Progress::of_items(&items),
+            budget: BudgetStatus::of_list(&list, &totals),
+            totals,
             id: list.id,
             user_id: list.user_id,
             title: list.title,
             subtitle: list.subtitle,
-            items,
+            created_at: list.created_at,
+            updated_at: list.updated_at,
+            items: ItemNode::tree(items),
         }
     }
 }
 
+// Api Profile Update Struct
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateMe {
+    pub nick: String,
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VerifyEmail {
+    pub token: String,
+}
+
+// Api Token Request Struct: derive a token with a subset of the caller's scopes
+#[derive(Debug, Deserialize, Clone)]
+pub struct CreateToken {
+    pub scopes: Vec<Scope>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TokenResponse {
+    pub user_id: i64,
+    pub token: String,
+}
+
+// Api Response Struct for a rejected duplicate, an error message naming the existing item
+#[derive(Debug, Serialize, Clone)]
+pub struct DuplicateItem {
+    pub code: u16,
+    pub message: String,
+    pub candidate: Item,
+}
+
+// Api Response Struct for POST /item
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum AddItemResponse {
+    Added(IdResponse),
+    Duplicate(Box<DuplicateItem>),
+}
+
+// Api Response Struct for GET /trash
+#[derive(Debug, Serialize, Clone)]
+pub struct Trash {
+    pub lists: Vec<List>,
+    pub items: Vec<Item>,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct IdResponse {
     pub id: i64,
@@ -77,12 +613,14 @@ impl IdResponse {
 }
 
 pub async fn add_list(
+    claims: Claims,
     db_manager: db::DBManager,
     new_list: AddList,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling add list");
 
-    let create_list_dto = new_list.to_dto();
+    // lists are always created for the caller
+    let create_list_dto = new_list.to_dto(claims.sub);
 
     let id_response = db_manager
         .create_list(create_list_dto)
@@ -91,22 +629,112 @@ pub async fn add_list(
     return respond(id_response, warp::http::StatusCode::CREATED);
 }
 
-pub async fn get_lists(db_manager: db::DBManager) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn get_lists(
+    query: ListsQuery,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling get lists");
 
-    let result = db_manager.get_lists();
+    let sort = query.sort.unwrap_or(ListSort::Id);
+    let order = query.order.unwrap_or(SortOrder::Asc);
+    let limit = pagination::page_size(query.limit);
+
+    let after = match &query.cursor {
+        Some(encoded) => match Cursor::decode(encoded, sort, order) {
+            Ok(cursor) => Some(cursor),
+            Err(err) => return respond(Err(err), warp::http::StatusCode::BAD_REQUEST),
+        },
+        None => None,
+    };
+
+    // fetch one more than requested to know whether there is a next page
+    let result = db_manager
+        .get_lists(sort, order, &query.to_filter(&claims), after, limit + 1)
+        .and_then(|mut lists| {
+            let next_cursor = if lists.len() as i64 > limit {
+                lists.truncate(limit as usize);
+                lists
+                    .last()
+                    .map(|list| Cursor::after_list(list, sort, order).encode())
+            } else {
+                None
+            };
+
+            let data = match query.include {
+                // all items of the page in a single query, progress is counted from them
+                Some(ListInclude::Items) => {
+                    let items = db_manager.get_items_grouped(&lists)?;
+                    lists
+                        .into_iter()
+                        .zip(items)
+                        .map(|(list, items)| ListWithProgress {
+                            progress: Progress::of_items(&items),
+                            list,
+                            items: Some(items),
+                        })
+                        .collect::<Vec<ListWithProgress>>()
+                }
+                Some(ListInclude::Summary) | None => {
+                    let mut progress =
+                        db_manager.get_progress(lists.iter().map(|list| list.id).collect())?;
+                    lists
+                        .into_iter()
+                        .map(|list| ListWithProgress {
+                            progress: progress.remove(&list.id).unwrap_or_default(),
+                            list,
+                            items: None,
+                        })
+                        .collect::<Vec<ListWithProgress>>()
+                }
+            };
+
+            Ok(Page {
+                data,
+                next: next_cursor
+                    .as_ref()
+                    .map(|cursor| query.next_link(sort, order, limit, cursor)),
+                next_cursor,
+            })
+        });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn search(
+    query: SearchQuery,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling search for user {}", claims.sub);
+
+    if query.q.trim().is_empty() {
+        return respond(
+            Err(ApiError::new("q: must not be empty", ErrorType::BadRequest)),
+            warp::http::StatusCode::BAD_REQUEST,
+        );
+    }
+
+    let result = db_manager.search(
+        claims.sub,
+        query.q.trim(),
+        pagination::page_size(query.limit),
+    );
 
     return respond(result, warp::http::StatusCode::OK);
 }
 
 pub async fn get_list(
     list_id: i64,
+    claims: Claims,
     db_manager: db::DBManager,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling get single list");
 
-    // retrieve list and associated items from db
-    let result = db_manager.get_list(list_id);
+    // retrieve list and associated items from db, lists of other users are not found
+    let result = db_manager
+        .check_list_access(&[list_id], &claims)
+        .and_then(|_| db_manager.get_list(list_id));
     match result {
         // list is found, return data and 200
         Ok((list, items)) => {
@@ -121,13 +749,16 @@ pub async fn get_list(
 
 pub async fn update_list(
     list_id: i64,
+    claims: Claims,
     db_manager: db::DBManager,
     updated_list: AddList,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling update status");
 
     let id_response = db_manager
-        .update_list(list_id, updated_list.title, updated_list.subtitle)
+        .revised(list_id, &claims, "update_list", || {
+            db_manager.update_list(list_id, updated_list.title, updated_list.subtitle)
+        })
         .map(|_| IdResponse::new(list_id));
 
     return respond(id_response, warp::http::StatusCode::OK);
@@ -135,55 +766,976 @@ pub async fn update_list(
 
 pub async fn delete_list(
     list_id: i64,
+    claims: Claims,
     db_manager: db::DBManager,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling delete list");
 
-    let result = db_manager.delete_list(list_id).map(|_| -> () { () });
+    let result = db_manager
+        .revised(list_id, &claims, "delete_list", || {
+            db_manager.delete_list(list_id)
+        })
+        .map(|_| ());
 
     return respond(result, warp::http::StatusCode::NO_CONTENT);
 }
 
+pub async fn set_list_budget(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: SetBudget,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("setting budget of list {}", list_id);
+
+    let budget = match request.validated() {
+        Ok(budget) => budget,
+        Err(err) => return respond(Err(err), warp::http::StatusCode::BAD_REQUEST),
+    };
+
+    let result = db_manager
+        .revised(list_id, &claims, "set_budget", || {
+            db_manager.set_list_budget(list_id, Some(budget))
+        })
+        .map(|_| IdResponse::new(list_id));
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn delete_list_budget(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("removing budget of list {}", list_id);
+
+    let result = db_manager
+        .revised(list_id, &claims, "remove_budget", || {
+            db_manager.set_list_budget(list_id, None)
+        })
+        .map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
+pub async fn get_tags(
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get tags for user {}", claims.sub);
+
+    let result = db_manager.get_tags(claims.sub);
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn add_tag(
+    claims: Claims,
+    db_manager: db::DBManager,
+    new_tag: TagName,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling add tag for user {}", claims.sub);
+
+    let result = new_tag.validated().and_then(|name| {
+        db_manager.create_tag(CreateTag {
+            user_id: claims.sub,
+            name,
+        })
+    });
+
+    return respond(result, warp::http::StatusCode::CREATED);
+}
+
+pub async fn rename_tag(
+    tag_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    renamed_tag: TagName,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("renaming tag {}", tag_id);
+
+    let result = renamed_tag
+        .validated()
+        .and_then(|name| db_manager.rename_tag(claims.sub, tag_id, name));
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn delete_tag(
+    tag_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("deleting tag {}", tag_id);
+
+    let result = db_manager.delete_tag(claims.sub, tag_id).map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
+pub async fn get_list_tags(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get tags of list {}", list_id);
+
+    let result = db_manager.get_list_tags(claims.sub, list_id);
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn tag_list(
+    list_id: i64,
+    tag_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("tagging list {} with tag {}", list_id, tag_id);
+
+    let result = db_manager.tag_list(claims.sub, list_id, tag_id).map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
+pub async fn untag_list(
+    list_id: i64,
+    tag_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("removing tag {} from list {}", tag_id, list_id);
+
+    let result = db_manager
+        .untag_list(claims.sub, list_id, tag_id)
+        .map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
+pub async fn clone_list(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: CloneList,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("cloning list {} for user {}", list_id, claims.sub);
+
+    let options = db::CloneOptions {
+        title: request.title,
+        reset_checked: request.reset_checked,
+        as_template: request.as_template,
+        from_template: false,
+    };
+    let result = db_manager
+        .clone_list(list_id, claims.sub, options)
+        .and_then(|list| db_manager.get_list(list.id))
+        .map(|(list, items)| ListWithItems::new(list, items));
+
+    return respond(result, warp::http::StatusCode::CREATED);
+}
+
+pub async fn get_templates(
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get templates for user {}", claims.sub);
+
+    let result = db_manager.get_templates(claims.sub);
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn instantiate_template(
+    template_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: InstantiateTemplate,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(
+        "instantiating template {} for user {}",
+        template_id,
+        claims.sub
+    );
+
+    // a fresh list from a template starts with nothing checked
+    let options = db::CloneOptions {
+        title: request.title,
+        reset_checked: true,
+        as_template: false,
+        from_template: true,
+    };
+    let result = db_manager
+        .clone_list(template_id, claims.sub, options)
+        .and_then(|list| db_manager.get_list(list.id))
+        .map(|(list, items)| ListWithItems::new(list, items));
+
+    return respond(result, warp::http::StatusCode::CREATED);
+}
+
+pub async fn set_recurrence(
+    template_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: SetRecurrence,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("setting recurrence of template {}", template_id);
+
+    let result = request
+        .to_dto(template_id)
+        .and_then(|dto| db_manager.set_recurrence(claims.sub, dto));
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn get_recurrence(
+    template_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get recurrence of template {}", template_id);
+
+    let result = db_manager.get_recurrence(claims.sub, template_id);
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn delete_recurrence(
+    template_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("deleting recurrence of template {}", template_id);
+
+    let result = db_manager
+        .delete_recurrence(claims.sub, template_id)
+        .map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
+pub async fn get_history(
+    list_id: i64,
+    claims: Claims,
+    query: HistoryQuery,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get history of list {}", list_id);
+
+    // the history of lists of other users is not found, like the lists themselves
+    let result = db_manager
+        .check_list_access(&[list_id], &claims)
+        .and_then(|_| {
+            db_manager.get_revisions(list_id, query.before, pagination::page_size(query.limit))
+        });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn revert_list(
+    list_id: i64,
+    revision: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("reverting list {} to revision {}", list_id, revision);
+
+    let result = db_manager
+        .revert_list(list_id, revision, &claims)
+        .and_then(|_| db_manager.get_list(list_id))
+        .map(|(list, items)| ListWithItems::new(list, items));
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn get_trash(
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get trash for user {}", claims.sub);
+
+    let result = db_manager
+        .get_trash(claims.sub)
+        .map(|(lists, items)| Trash { lists, items });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn restore(
+    kind: TrashKind,
+    id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling restore {:?} for user {}", kind, claims.sub);
+
+    // restoring needs the same scope as deleting did
+    let scope = match kind {
+        TrashKind::List => Scope::ListsWrite,
+        TrashKind::Item => Scope::ItemsWrite,
+    };
+    if !claims.has_scope(scope) {
+        return respond(
+            Err(ApiError::new(
+                format!("Token is missing the required scope {:?}", scope).as_str(),
+                ErrorType::Forbidden,
+            )),
+            warp::http::StatusCode::FORBIDDEN,
+        );
+    }
+
+    let result = match kind {
+        TrashKind::List => db_manager.revised(id, &claims, "restore_list", || {
+            db_manager.restore_list(claims.sub, id).map(|list| list.id)
+        }),
+        TrashKind::Item => db_manager.get_item_list_id(id).and_then(|list_id| {
+            db_manager.revised(list_id, &claims, "restore_item", || {
+                db_manager.restore_item(claims.sub, id).map(|item| item.id)
+            })
+        }),
+    };
+
+    return respond(result.map(IdResponse::new), warp::http::StatusCode::OK);
+}
+
+pub async fn reorder_items(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    order: ReorderItems,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("reordering items of list {}", list_id);
+
+    let id_response = db_manager
+        .revised(list_id, &claims, "reorder_items", || {
+            db_manager.reorder_items(list_id, order.item_ids)
+        })
+        .map(|_| IdResponse::new(list_id));
+
+    return respond(id_response, warp::http::StatusCode::OK);
+}
+
 pub async fn add_item(
+    claims: Claims,
     db_manager: db::DBManager,
     new_item: AddItem,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling add item");
 
-    let create_item = new_item.to_dto();
+    if let Err(err) = validate_quantity(&new_item.quantity) {
+        return respond(Err(err), warp::http::StatusCode::BAD_REQUEST);
+    }
 
-    let id_response = db_manager
-        .create_item(create_item)
-        .map(|list| IdResponse::new(list.id));
+    let policy = match DuplicatePolicy::new(new_item.on_duplicate, new_item.similarity) {
+        Ok(policy) => policy,
+        Err(err) => return respond(Err(err), warp::http::StatusCode::BAD_REQUEST),
+    };
+    let create_item = match new_item.to_dto() {
+        Ok(create_item) => create_item,
+        Err(err) => return respond(Err(err), warp::http::StatusCode::BAD_REQUEST),
+    };
 
-    return respond(id_response, warp::http::StatusCode::CREATED);
+    // adding a product already on the list adds up the quantities unless asked otherwise
+    match db_manager.revised(new_item.list_id, &claims, "add_item", || {
+        db_manager.add_item(create_item, new_item.position, policy)
+    }) {
+        Ok(AddedItem::Created(item)) => respond(
+            Ok(AddItemResponse::Added(IdResponse::new(item.id))),
+            warp::http::StatusCode::CREATED,
+        ),
+        Ok(AddedItem::Merged(item)) => respond(
+            Ok(AddItemResponse::Added(IdResponse::new(item.id))),
+            warp::http::StatusCode::OK,
+        ),
+        Ok(AddedItem::Duplicate(item)) => respond(
+            Ok(AddItemResponse::Duplicate(Box::new(DuplicateItem {
+                code: warp::http::StatusCode::CONFLICT.as_u16(),
+                message: String::from("An item like this is already on the list"),
+                candidate: item,
+            }))),
+            warp::http::StatusCode::CONFLICT,
+        ),
+        Err(err) => respond(Err(err), warp::http::StatusCode::CREATED),
+    }
+}
+
+/// run one operation of a batch, items to update or delete have to be on the list
+fn run_batch_operation(
+    db_manager: &db::DBManager,
+    list_id: i64,
+    operation: BatchOperation,
+) -> Result<BatchResult, ApiError> {
+    let ensure_on_list = |item_id: i64| match db_manager.get_item_list_id(item_id)? {
+        item_list_id if item_list_id == list_id => Ok(()),
+        _ => Err(ApiError::new(
+            "Item not found on this list",
+            ErrorType::NotFound,
+        )),
+    };
+
+    match operation {
+        BatchOperation::Create {
+            title,
+            quantity,
+            unit,
+            position,
+            parent_item_id,
+            due_at,
+            remind_at,
+            details,
+            on_duplicate,
+            similarity,
+        } => {
+            validate_quantity(&quantity)?;
+            let policy = DuplicatePolicy::new(on_duplicate, similarity)?;
+            let create_item = CreateItem {
+                list_id,
+                title: title.trim().to_string(),
+                quantity,
+                unit,
+                parent_item_id,
+                due_at,
+                remind_at,
+                details: details.validated()?,
+            };
+            let (item, status) = match db_manager.add_item(create_item, position, policy)? {
+                AddedItem::Created(item) => (item, warp::http::StatusCode::CREATED),
+                AddedItem::Merged(item) => (item, warp::http::StatusCode::OK),
+                AddedItem::Duplicate(item) => {
+                    return Err(ApiError::new(
+                        format!("duplicate of item {}", item.id).as_str(),
+                        ErrorType::Conflict,
+                    ))
+                }
+            };
+            Ok(BatchResult {
+                op: "create",
+                id: item.id,
+                status: status.as_u16(),
+            })
+        }
+        BatchOperation::Update {
+            id,
+            title,
+            quantity,
+            unit,
+        } => {
+            validate_quantity(&quantity)?;
+            ensure_on_list(id)?;
+            db_manager.update_item(id, title, quantity, unit)?;
+            Ok(BatchResult {
+                op: "update",
+                id,
+                status: warp::http::StatusCode::OK.as_u16(),
+            })
+        }
+        BatchOperation::Delete { id } => {
+            ensure_on_list(id)?;
+            db_manager.delete_item(id)?;
+            Ok(BatchResult {
+                op: "delete",
+                id,
+                status: warp::http::StatusCode::NO_CONTENT.as_u16(),
+            })
+        }
+    }
+}
+
+pub async fn batch_items(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    batch: BatchItems,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(
+        "handling batch of {} item operations on list {}",
+        batch.operations.len(),
+        list_id
+    );
+
+    if batch.operations.len() > MAX_BATCH_OPERATIONS {
+        return respond(
+            Err(ApiError::new(
+                format!("operations: at most {} per batch", MAX_BATCH_OPERATIONS).as_str(),
+                ErrorType::BadRequest,
+            )),
+            warp::http::StatusCode::BAD_REQUEST,
+        );
+    }
+
+    // all operations run in one transaction, the first failure rolls back the whole batch
+    let result = db_manager.revised(list_id, &claims, "batch_items", || {
+        batch
+            .operations
+            .into_iter()
+            .enumerate()
+            .map(|(index, operation)| {
+                run_batch_operation(&db_manager, list_id, operation).map_err(|err| {
+                    ApiError::new(
+                        format!("operations[{}]: {}", index, err.message).as_str(),
+                        err.err_type,
+                    )
+                })
+            })
+            .collect::<Result<Vec<BatchResult>, ApiError>>()
+    });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+/// move or copy items into a list, both the lists of the items and the target must belong to the caller
+fn transfer_items(
+    claims: Claims,
+    db_manager: db::DBManager,
+    item_ids: Vec<i64>,
+    target_list_id: i64,
+    position: Option<usize>,
+    copy: bool,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if item_ids.is_empty() {
+        return respond(
+            Err(ApiError::new(
+                "item_ids: must not be empty",
+                ErrorType::BadRequest,
+            )),
+            warp::http::StatusCode::BAD_REQUEST,
+        );
+    }
+
+    // copying leaves the source lists as they are
+    let (action, mut changed_lists, status) = if copy {
+        ("copy_items", vec![], warp::http::StatusCode::CREATED)
+    } else {
+        match db_manager.get_items_list_ids(&item_ids) {
+            Ok(source_lists) => ("move_items", source_lists, warp::http::StatusCode::OK),
+            Err(err) => return respond(Err(err), warp::http::StatusCode::OK),
+        }
+    };
+    changed_lists.push(target_list_id);
+
+    let result = db_manager.revised_many(&changed_lists, &claims, action, || {
+        db_manager.transfer_items(claims.sub, &item_ids, target_list_id, position, copy)
+    });
+
+    return respond(result, status);
+}
+
+pub async fn move_item(
+    item_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: TransferItem,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("moving item {} to list {}", item_id, request.list_id);
+
+    transfer_items(
+        claims,
+        db_manager,
+        vec![item_id],
+        request.list_id,
+        request.position,
+        false,
+    )
+}
+
+pub async fn copy_item(
+    item_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: TransferItem,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("copying item {} to list {}", item_id, request.list_id);
+
+    transfer_items(
+        claims,
+        db_manager,
+        vec![item_id],
+        request.list_id,
+        request.position,
+        true,
+    )
+}
+
+pub async fn move_items(
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: TransferItems,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(
+        "moving {} items to list {}",
+        request.item_ids.len(),
+        request.list_id
+    );
+
+    transfer_items(
+        claims,
+        db_manager,
+        request.item_ids,
+        request.list_id,
+        request.position,
+        false,
+    )
+}
+
+pub async fn copy_items(
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: TransferItems,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(
+        "copying {} items to list {}",
+        request.item_ids.len(),
+        request.list_id
+    );
+
+    transfer_items(
+        claims,
+        db_manager,
+        request.item_ids,
+        request.list_id,
+        request.position,
+        true,
+    )
 }
 
 pub async fn update_item(
     item_id: i64,
+    claims: Claims,
     db_manager: db::DBManager,
     updated_item: UpdateItem,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("updating item {}", item_id);
 
+    if let Err(err) = validate_quantity(&updated_item.quantity) {
+        return respond(Err(err), warp::http::StatusCode::BAD_REQUEST);
+    }
+
     let id_response = db_manager
-        .update_item(item_id, updated_item.title, updated_item.amount)
+        .get_item_list_id(item_id)
+        .and_then(|list_id| {
+            db_manager.revised(list_id, &claims, "update_item", || {
+                db_manager.update_item(
+                    item_id,
+                    updated_item.title,
+                    updated_item.quantity,
+                    updated_item.unit,
+                )
+            })
+        })
         .map(|_| IdResponse::new(item_id));
 
     return respond(id_response, warp::http::StatusCode::OK);
 }
 
+pub async fn set_item_parent(
+    item_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: SetParent,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(
+        "nesting item {} under {:?}",
+        item_id,
+        request.parent_item_id
+    );
+
+    let result = db_manager.get_item_list_id(item_id).and_then(|list_id| {
+        db_manager.revised(list_id, &claims, "nest_item", || {
+            db_manager.set_item_parent(item_id, request.parent_item_id)
+        })
+    });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn set_item_schedule(
+    item_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: SetSchedule,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("scheduling item {}", item_id);
+
+    let result = db_manager.get_item_list_id(item_id).and_then(|list_id| {
+        db_manager.revised(list_id, &claims, "schedule_item", || {
+            db_manager.set_item_schedule(item_id, request.due_at, request.remind_at)
+        })
+    });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn set_item_details(
+    item_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+    request: SetDetails,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("updating details of item {}", item_id);
+
+    let details = match request.validated() {
+        Ok(details) => details,
+        Err(err) => return respond(Err(err), warp::http::StatusCode::BAD_REQUEST),
+    };
+
+    let result = db_manager.get_item_list_id(item_id).and_then(|list_id| {
+        db_manager.revised(list_id, &claims, "update_item_details", || {
+            db_manager.set_item_details(item_id, details)
+        })
+    });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn get_due_items(
+    query: DueQuery,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get due items for user {}", claims.sub);
+
+    let result = db_manager
+        .get_due_items(
+            claims.sub,
+            query.before.unwrap_or_else(Utc::now),
+            pagination::page_size(query.limit),
+        )
+        .map(|due| {
+            due.into_iter()
+                .map(|(item, list)| DueItem {
+                    item,
+                    list_title: list.title,
+                })
+                .collect::<Vec<DueItem>>()
+        });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn toggle_item(
+    item_id: i64,
+    query: ToggleQuery,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("toggling item {}", item_id);
+
+    let cascade = query.cascade.unwrap_or(false);
+    let result = db_manager.get_item_list_id(item_id).and_then(|list_id| {
+        db_manager.revised(list_id, &claims, "toggle_item", || {
+            db_manager.toggle_item(item_id, claims.sub, cascade)
+        })
+    });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn clear_checked_items(
+    list_id: i64,
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("clearing checked items of list {}", list_id);
+
+    let result = db_manager
+        .revised(list_id, &claims, "clear_checked_items", || {
+            db_manager.clear_checked_items(list_id)
+        })
+        .map(|_| ());
+
+    return respond(result, warp::http::StatusCode::NO_CONTENT);
+}
+
 pub async fn delete_item(
     item_id: i64,
+    claims: Claims,
     db_manager: db::DBManager,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("deleting item {}", item_id);
 
-    let result = db_manager.delete_item(item_id).map(|_| -> () { () });
+    let result = db_manager
+        .get_item_list_id(item_id)
+        .and_then(|list_id| {
+            db_manager.revised(list_id, &claims, "delete_item", || {
+                db_manager.delete_item(item_id)
+            })
+        })
+        .map(|_| ());
 
     return respond(result, warp::http::StatusCode::NO_CONTENT);
 }
 
+pub async fn get_me(
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling get me for user {}", claims.sub);
+
+    let result = db_manager.get_user(claims.sub);
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn update_me(
+    claims: Claims,
+    db_manager: db::DBManager,
+    updated_user: UpdateMe,
+    auth: Arc<Auth>,
+    actor: Arc<WebauthnActor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling update me for user {}", claims.sub);
+
+    let user = match db_manager.get_user(claims.sub) {
+        Ok(user) => user,
+        Err(err) => return respond(Err(err), warp::http::StatusCode::NOT_FOUND),
+    };
+
+    let new_nick = normalize_nick(&updated_user.nick);
+    let new_email = normalize_email(&updated_user.email);
+
+    // a changed email only becomes active after it has been verified
+    let pending_email = if new_email == user.email {
+        None
+    } else {
+        Some(new_email)
+    };
+
+    let result = db_manager.update_user(claims.sub, new_nick, pending_email);
+
+    if let Ok(updated) = &result {
+        if updated.nick != user.nick {
+            actor.rename_credentials(&user.nick, &updated.nick).await;
+        }
+        if let Some(email) = &updated.pending_email {
+            // the token is delivered to the new address by the notifier, it never shows up in the log
+            let queued = auth
+                .issue_email_token(updated.id, email.clone())
+                .and_then(|token| {
+                    db_manager.queue_notification(&Notification::email_verification(
+                        updated.id,
+                        email.clone(),
+                        token,
+                    ))
+                });
+            if let Err(err) = queued {
+                return respond(Err(err), warp::http::StatusCode::OK);
+            }
+        }
+    }
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn verify_email(
+    claims: Claims,
+    db_manager: db::DBManager,
+    verification: VerifyEmail,
+    auth: Arc<Auth>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling verify email for user {}", claims.sub);
+
+    let result = match auth.verify_email_token(verification.token.as_str()) {
+        Ok(email_claims) if email_claims.sub == claims.sub => {
+            db_manager.confirm_email(claims.sub, email_claims.email)
+        }
+        Ok(_) => Err(ApiError::new(
+            "Email token was issued for another user",
+            ErrorType::Forbidden,
+        )),
+        Err(err) => Err(err),
+    };
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
+pub async fn export_me(
+    claims: Claims,
+    db_manager: db::DBManager,
+    actor: Arc<WebauthnActor>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling export for user {}", claims.sub);
+
+    let user = match db_manager.get_user(claims.sub) {
+        Ok(user) => user,
+        Err(err) => return Err(warp::reject::custom(err)),
+    };
+    let credentials = actor.credentials_metadata(&user.nick).await;
+    let filename = format!("retrolist-export-{}.jsonl", user.id);
+
+    let body = export::stream_export(user, credentials, db_manager);
+
+    warp::http::Response::builder()
+        .header("content-type", "application/x-ndjson")
+        .header(
+            "content-disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(body)
+        .map_err(|err| {
+            warp::reject::custom(ApiError::new(
+                format!("while building export response: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            ))
+        })
+}
+
+pub async fn delete_me(
+    claims: Claims,
+    db_manager: db::DBManager,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling delete me for user {}", claims.sub);
+
+    // the account is only marked here, signing in during the grace period cancels the deletion
+    let result = db_manager.request_user_deletion(claims.sub);
+
+    return respond(result, warp::http::StatusCode::ACCEPTED);
+}
+
+pub async fn create_token(
+    claims: Claims,
+    request: CreateToken,
+    auth: Arc<Auth>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling create token for user {}", claims.sub);
+
+    // a derived token never outlives the token it was derived from
+    let remaining = chrono::Duration::seconds(claims.exp - Utc::now().timestamp());
+    let lifetime = match request.expires_in_hours {
+        Some(hours) if hours < 1 || hours > remaining.num_hours() => Err(ApiError::new(
+            format!(
+                "expires_in_hours must be between 1 and {}",
+                remaining.num_hours()
+            )
+            .as_str(),
+            ErrorType::BadRequest,
+        )),
+        Some(hours) => Ok(chrono::Duration::hours(hours)),
+        None => Ok(remaining),
+    };
+
+    // a token can only hand out scopes its bearer already holds
+    let result = lifetime.and_then(|lifetime| {
+        match request
+            .scopes
+            .iter()
+            .find(|scope| !claims.has_scope(**scope))
+        {
+            Some(scope) => Err(ApiError::new(
+                format!("Cannot grant scope {:?} not held by the caller", scope).as_str(),
+                ErrorType::Forbidden,
+            )),
+            None => auth
+                .issue_token(claims.sub, request.scopes, Some(lifetime))
+                .map(|token| TokenResponse {
+                    user_id: claims.sub,
+                    token,
+                }),
+        }
+    });
+
+    return respond(result, warp::http::StatusCode::CREATED);
+}
+
 fn respond<T: Serialize>(
     result: Result<T, ApiError>,
     status: warp::http::StatusCode,
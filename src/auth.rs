@@ -0,0 +1,220 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::Filter;
+
+use crate::errors::{ApiError, ErrorType};
+
+const EMAIL_TOKEN_LIFETIME_HOURS: i64 = 48;
+
+/// Permissions that can be attached to a token
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "lists:read")]
+    ListsRead,
+    #[serde(rename = "lists:write")]
+    ListsWrite,
+    #[serde(rename = "items:write")]
+    ItemsWrite,
+    #[serde(rename = "profile")]
+    Profile,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    /// the scopes every user gets for their own data, admin is only granted to ADMIN_USER_IDS
+    pub fn user() -> Vec<Scope> {
+        vec![
+            Scope::ListsRead,
+            Scope::ListsWrite,
+            Scope::ItemsWrite,
+            Scope::Profile,
+        ]
+    }
+}
+
+/// JWT payload: the user id and the scopes granted to the bearer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+    pub sub: i64,
+    pub exp: i64,
+    pub scopes: Vec<Scope>,
+}
+
+impl Claims {
+    /// admin implies every other scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+/// JWT payload proving control over a new email address
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmailClaims {
+    pub sub: i64,
+    pub exp: i64,
+    pub email: String,
+}
+
+pub struct Auth {
+    secret: String,
+    token_lifetime: Duration,
+    admin_ids: Vec<i64>,
+}
+
+impl Auth {
+    pub fn new(secret: String, token_lifetime: Duration, admin_ids: Vec<i64>) -> Self {
+        Auth {
+            secret,
+            token_lifetime,
+            admin_ids,
+        }
+    }
+
+    /// the scopes a user signing in is granted, admin only for the configured admins
+    pub fn scopes_for(&self, user_id: i64) -> Vec<Scope> {
+        let mut scopes = Scope::user();
+        if self.admin_ids.contains(&user_id) {
+            scopes.push(Scope::Admin);
+        }
+        scopes
+    }
+
+    /// sign a token for the user with the given scopes, optionally with a custom lifetime
+    pub fn issue_token(
+        &self,
+        user_id: i64,
+        scopes: Vec<Scope>,
+        lifetime: Option<Duration>,
+    ) -> Result<String, ApiError> {
+        let exp = Utc::now()
+            .checked_add_signed(lifetime.unwrap_or(self.token_lifetime))
+            .ok_or_else(|| ApiError::new("Token lifetime out of range", ErrorType::BadRequest))?;
+        let claims = Claims {
+            sub: user_id,
+            exp: exp.timestamp(),
+            scopes,
+        };
+
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.secret.as_bytes()),
+        )
+        .map_err(|err| {
+            ApiError::new(
+                format!("while issuing token: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })
+    }
+
+    /// sign a short lived token confirming the user owns the given email address
+    pub fn issue_email_token(&self, user_id: i64, email: String) -> Result<String, ApiError> {
+        let claims = EmailClaims {
+            sub: user_id,
+            exp: (Utc::now() + Duration::hours(EMAIL_TOKEN_LIFETIME_HOURS)).timestamp(),
+            email,
+        };
+
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.secret.as_bytes()),
+        )
+        .map_err(|err| {
+            ApiError::new(
+                format!("while issuing email token: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })
+    }
+
+    /// check an email verification token and return its claims
+    pub fn verify_email_token(&self, token: &str) -> Result<EmailClaims, ApiError> {
+        decode::<EmailClaims>(
+            token,
+            &DecodingKey::from_secret(self.secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map(|data| data.claims)
+        .map_err(|err| {
+            ApiError::new(
+                format!("Invalid email token: {}", err.to_string()).as_str(),
+                ErrorType::BadRequest,
+            )
+        })
+    }
+
+    /// validate the Authorization header and return the claims of the bearer token
+    pub fn authenticate(&self, header: Option<String>) -> Result<Claims, ApiError> {
+        let header = header.ok_or_else(|| {
+            ApiError::new("Missing Authorization header", ErrorType::Unauthorized)
+        })?;
+
+        let token = match header.strip_prefix("Bearer ") {
+            Some(token) => token.trim(),
+            None => {
+                return Err(ApiError::new(
+                    "Authorization header must be a Bearer token",
+                    ErrorType::Unauthorized,
+                ))
+            }
+        };
+
+        decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(self.secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map(|data| data.claims)
+        .map_err(|err| {
+            ApiError::new(
+                format!("Invalid token: {}", err.to_string()).as_str(),
+                ErrorType::Unauthorized,
+            )
+        })
+    }
+
+    /// validate the bearer token and make sure it carries the required scope
+    pub fn authorize(&self, header: Option<String>, scope: Scope) -> Result<Claims, ApiError> {
+        let claims = self.authenticate(header)?;
+
+        if !claims.has_scope(scope) {
+            return Err(ApiError::new(
+                format!("Token is missing the required scope {:?}", scope).as_str(),
+                ErrorType::Forbidden,
+            ));
+        }
+        Ok(claims)
+    }
+}
+
+pub fn with_auth(
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = (Arc<Auth>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || auth.clone())
+}
+
+/// Extract the claims of any valid bearer token
+pub fn with_claims(
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = (Claims,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let auth = auth.clone();
+        async move { auth.authenticate(header).map_err(warp::reject::custom) }
+    })
+}
+
+/// Extract the claims of a bearer token carrying the given scope
+pub fn with_scope(
+    auth: Arc<Auth>,
+    scope: Scope,
+) -> impl Filter<Extract = (Claims,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let auth = auth.clone();
+        async move { auth.authorize(header, scope).map_err(warp::reject::custom) }
+    })
+}
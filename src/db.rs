@@ -1,13 +1,125 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, PooledConnection};
+use std::collections::HashMap;
 
+use crate::auth::{Claims, Scope};
 use crate::errors::{ApiError, ErrorType};
-use crate::models::{CreateItem, Item};
-use crate::models::{CreateList, List};
-use crate::models::{CreateUser, User};
+use crate::history::ListSnapshot;
+use crate::matching::{title_similarity, DuplicatePolicy, OnDuplicate};
+use crate::models::SearchHit;
+use crate::models::{max_quantity, normalize_nick, CreateUser, User};
+use crate::models::{BudgetStatus, CreateNotification, PendingNotification, Totals};
+use crate::models::{CreateItem, Item, ItemDetails, Progress, Unit};
+use crate::models::{CreateList, List, ListFilter, ListSort, SortOrder};
+use crate::models::{CreateRecurrence, CreateRevision, Recurrence, Revision, StoredRevision};
+use crate::models::{CreateTag, ListTag, Tag};
+use crate::notify::Notification;
+use crate::pagination::{Cursor, CursorValue};
+use crate::recurrence::RecurrenceRule;
+use crate::schema::lists;
 
 type PooledPg = PooledConnection<ConnectionManager<PgConnection>>;
 
+type LiveListIds = diesel::dsl::Select<
+    diesel::dsl::Filter<lists::table, diesel::dsl::IsNull<lists::deleted_at>>,
+    lists::id,
+>;
+
+/// ids of the lists that are not in the trash, items of trashed lists can not be changed
+fn live_list_ids() -> LiveListIds {
+    lists::table
+        .filter(lists::deleted_at.is_null())
+        .select(lists::id)
+}
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+/// escape the wildcards of a LIKE pattern so user input matches literally
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// What `add_item` did with the new item
+#[derive(Debug, Clone)]
+pub enum AddedItem {
+    Created(Item),
+    // the existing item the quantity was added to
+    Merged(Item),
+    // the existing item that kept the new one from being added
+    Duplicate(Item),
+}
+
+/// How a list is copied by `clone_list`
+#[derive(Debug, Clone)]
+pub struct CloneOptions {
+    // keeps the title of the source if not given
+    pub title: Option<String>,
+    pub reset_checked: bool,
+    pub as_template: bool,
+    pub from_template: bool,
+}
+
+/// spacing between item positions, leaves room to insert items without renumbering the list
+const POSITION_GAP: i64 = 1024;
+
+/// levels items can be nested in, top level items are on the first
+const MAX_ITEM_DEPTH: usize = 5;
+
+/// position for an item inserted before the one at `index` (appended if out of range),
+/// None if there is no gap left between the neighbours
+fn position_at(positions: &[i64], index: usize) -> Option<i64> {
+    if index >= positions.len() {
+        return Some(
+            positions
+                .last()
+                .map_or(POSITION_GAP, |last| last + POSITION_GAP),
+        );
+    }
+
+    let before = if index == 0 { 0 } else { positions[index - 1] };
+    let after = positions[index];
+    if after - before > 1 {
+        Some(before + (after - before) / 2)
+    } else {
+        None
+    }
+}
+
+/// Sets the text search configuration used by the search triggers and queries on every new connection
+#[derive(Debug)]
+pub struct SearchLanguage {
+    language: String,
+}
+
+impl SearchLanguage {
+    /// the name ends up in a SET statement, only plain identifiers are accepted
+    pub fn new(language: &str) -> Option<SearchLanguage> {
+        if !language.is_empty()
+            && language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            Some(SearchLanguage {
+                language: language.to_string(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for SearchLanguage {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.execute(format!("SET retrolist.search_language = '{}'", self.language).as_str())
+            .map(|_| ())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
 pub struct DBManager {
     connection: PooledPg,
 }
@@ -27,19 +139,97 @@ impl DBManager {
         // if error occurred map it to ApiError
     }
 
-    /// retrieve one user from the db
-    pub fn get_user_by_email(&self, by_email: String) -> Result<User, ApiError> {
+    /// retrieve one user by nick, ignoring case
+    pub fn get_user_by_nick(&self, by_nick: &str) -> Result<User, ApiError> {
         use super::schema::users::dsl::*;
 
-        match users
-            .filter(email.eq(by_email))
+        users
+            .filter(lower(nick).eq(normalize_nick(by_nick).to_lowercase()))
             .first::<User>(&self.connection)
-        {
-            Ok(user) => return Ok(user),
-            Err(err) => {
-                return Err(ApiError::from_diesel_err(err, "while loading user"));
-            }
-        }
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading user"))
+    }
+
+    /// retrieve one user by id
+    pub fn get_user(&self, user_id: i64) -> Result<User, ApiError> {
+        use super::schema::users::dsl::*;
+
+        users
+            .find(user_id)
+            .first::<User>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading user"))
+    }
+
+    /// update nick and the email awaiting verification, the current email stays until verified
+    pub fn update_user(
+        &self,
+        user_id: i64,
+        new_nick: String,
+        new_pending_email: Option<String>,
+    ) -> Result<User, ApiError> {
+        use super::schema::users::dsl::*;
+
+        diesel::update(users.find(user_id))
+            .set((nick.eq(new_nick), pending_email.eq(new_pending_email)))
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while updating user"))
+    }
+
+    /// swap in the pending email once it has been verified
+    pub fn confirm_email(&self, user_id: i64, verified_email: String) -> Result<User, ApiError> {
+        use super::schema::users::dsl::*;
+
+        diesel::update(
+            users
+                .find(user_id)
+                .filter(pending_email.eq(verified_email.clone())),
+        )
+        .set((email.eq(verified_email), pending_email.eq(None::<String>)))
+        .get_result(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while confirming email"))
+    }
+
+    /// mark a user for deletion, the account is purged once the grace period has expired
+    pub fn request_user_deletion(&self, user_id: i64) -> Result<User, ApiError> {
+        use super::schema::users::dsl::*;
+
+        diesel::update(users.find(user_id))
+            .set(deletion_requested_at.eq(Utc::now()))
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while requesting user deletion"))
+    }
+
+    /// signing in again within the grace period keeps the account
+    pub fn cancel_user_deletion(&self, by_nick: &str) -> Result<usize, ApiError> {
+        use super::schema::users::dsl::*;
+
+        diesel::update(
+            users
+                .filter(lower(nick).eq(normalize_nick(by_nick).to_lowercase()))
+                .filter(deletion_requested_at.is_not_null()),
+        )
+        .set(deletion_requested_at.eq(None::<DateTime<Utc>>))
+        .execute(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while cancelling user deletion"))
+    }
+
+    /// delete all users whose deletion was requested before the cutoff,
+    /// their lists and items are removed by the cascading foreign keys
+    pub fn purge_users(&self, requested_before: DateTime<Utc>) -> Result<Vec<User>, ApiError> {
+        use super::schema::users::dsl::*;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let expired_users = users
+                    .filter(deletion_requested_at.lt(requested_before))
+                    .for_update()
+                    .load::<User>(&self.connection)?;
+                let user_ids: Vec<i64> = expired_users.iter().map(|user| user.id).collect();
+
+                diesel::delete(users.filter(id.eq_any(user_ids))).execute(&self.connection)?;
+
+                Ok(expired_users)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while purging users"))
     }
 
     pub fn create_list(&self, dto: CreateList) -> Result<List, ApiError> {
@@ -52,21 +242,611 @@ impl DBManager {
         // if error occurred map it to ApiError
     }
 
-    /// retrieve all lists from the db
-    pub fn get_lists(&self) -> Result<Vec<List>, ApiError> {
+    /// retrieve one page of lists matching the filter in the requested order,
+    /// starting after the cursor. Keyset pagination keeps pages stable while lists change.
+    pub fn get_lists(
+        &self,
+        sort: ListSort,
+        order: SortOrder,
+        filter: &ListFilter,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Vec<List>, ApiError> {
+        use super::schema::items;
         use super::schema::lists::dsl::*;
+        use super::schema::{list_tags, tags};
 
-        lists
+        let mut query = lists
+            .filter(deleted_at.is_null())
+            .filter(is_template.eq(false))
+            .into_boxed();
+
+        if let Some(owner_id) = filter.owner_id {
+            query = query.filter(user_id.eq(owner_id));
+        }
+
+        if let Some(text) = &filter.title_contains {
+            query = query.filter(title.ilike(format!("%{}%", escape_like(text))));
+        }
+
+        if let Some(tag_name) = &filter.tag {
+            // without an owner the tag matches nothing
+            let tagged_lists = list_tags::table
+                .inner_join(tags::table)
+                .filter(tags::user_id.nullable().eq(filter.tag_owner_id))
+                .filter(lower(tags::name).eq(tag_name.trim().to_lowercase()))
+                .select(list_tags::list_id);
+            query = query.filter(id.eq_any(tagged_lists));
+        }
+
+        let lists_with_unchecked = items::table
+            .filter(items::checked.eq(false))
+            .filter(items::deleted_at.is_null())
+            .select(items::list_id);
+        query = match filter.has_unchecked {
+            Some(true) => query.filter(id.eq_any(lists_with_unchecked)),
+            Some(false) => query.filter(diesel::dsl::not(id.eq_any(lists_with_unchecked))),
+            None => query,
+        };
+
+        if let Some(cursor) = after {
+            let after_id = cursor.id;
+            query = match (cursor.value, order) {
+                (CursorValue::Id, SortOrder::Asc) => query.filter(id.gt(after_id)),
+                (CursorValue::Id, SortOrder::Desc) => query.filter(id.lt(after_id)),
+                (CursorValue::Title(value), SortOrder::Asc) => query.filter(
+                    title
+                        .gt(value.clone())
+                        .or(title.eq(value).and(id.gt(after_id))),
+                ),
+                (CursorValue::Title(value), SortOrder::Desc) => query.filter(
+                    title
+                        .lt(value.clone())
+                        .or(title.eq(value).and(id.lt(after_id))),
+                ),
+                (CursorValue::Timestamp(value), SortOrder::Asc) if sort == ListSort::Created => {
+                    query.filter(
+                        created_at
+                            .gt(value)
+                            .or(created_at.eq(value).and(id.gt(after_id))),
+                    )
+                }
+                (CursorValue::Timestamp(value), SortOrder::Desc) if sort == ListSort::Created => {
+                    query.filter(
+                        created_at
+                            .lt(value)
+                            .or(created_at.eq(value).and(id.lt(after_id))),
+                    )
+                }
+                (CursorValue::Timestamp(value), SortOrder::Asc) => query.filter(
+                    updated_at
+                        .gt(value)
+                        .or(updated_at.eq(value).and(id.gt(after_id))),
+                ),
+                (CursorValue::Timestamp(value), SortOrder::Desc) => query.filter(
+                    updated_at
+                        .lt(value)
+                        .or(updated_at.eq(value).and(id.lt(after_id))),
+                ),
+            };
+        }
+
+        query = match (sort, order) {
+            (ListSort::Id, SortOrder::Asc) => query.order(id.asc()),
+            (ListSort::Id, SortOrder::Desc) => query.order(id.desc()),
+            (ListSort::Title, SortOrder::Asc) => query.order((title.asc(), id.asc())),
+            (ListSort::Title, SortOrder::Desc) => query.order((title.desc(), id.desc())),
+            (ListSort::Created, SortOrder::Asc) => query.order((created_at.asc(), id.asc())),
+            (ListSort::Created, SortOrder::Desc) => query.order((created_at.desc(), id.desc())),
+            (ListSort::Updated, SortOrder::Asc) => query.order((updated_at.asc(), id.asc())),
+            (ListSort::Updated, SortOrder::Desc) => query.order((updated_at.desc(), id.desc())),
+        };
+
+        query
+            .limit(limit)
             .load(&self.connection)
             .map_err(|err| ApiError::from_diesel_err(err, "while listing lists"))
     }
 
+    /// the tags of a user, by name
+    pub fn get_tags(&self, owner_id: i64) -> Result<Vec<Tag>, ApiError> {
+        use super::schema::tags::dsl::*;
+
+        tags.filter(user_id.eq(owner_id))
+            .order((lower(name).asc(), id.asc()))
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading tags"))
+    }
+
+    pub fn create_tag(&self, dto: CreateTag) -> Result<Tag, ApiError> {
+        use super::schema::tags;
+
+        diesel::insert_into(tags::table)
+            .values(&dto)
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while creating tag"))
+    }
+
+    pub fn rename_tag(
+        &self,
+        owner_id: i64,
+        tag_id: i64,
+        new_name: String,
+    ) -> Result<Tag, ApiError> {
+        use super::schema::tags::dsl::*;
+
+        diesel::update(tags.find(tag_id).filter(user_id.eq(owner_id)))
+            .set(name.eq(new_name))
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while renaming tag"))
+    }
+
+    /// delete a tag of the user, it comes off all lists
+    pub fn delete_tag(&self, owner_id: i64, tag_id: i64) -> Result<usize, ApiError> {
+        use super::schema::tags::dsl::*;
+
+        let deleted = diesel::delete(tags.find(tag_id).filter(user_id.eq(owner_id)))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while deleting tag"))?;
+
+        if deleted == 0 {
+            return Err(ApiError::new("Tag not found", ErrorType::NotFound));
+        }
+        return Ok(deleted);
+    }
+
+    /// the tags on one of the user's lists, by name
+    pub fn get_list_tags(&self, owner_id: i64, of_list_id: i64) -> Result<Vec<Tag>, ApiError> {
+        use super::schema::{list_tags, lists, tags};
+
+        lists::table
+            .find(of_list_id)
+            .filter(lists::user_id.eq(owner_id))
+            .filter(lists::deleted_at.is_null())
+            .select(lists::id)
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+
+        tags::table
+            .inner_join(list_tags::table)
+            .filter(list_tags::list_id.eq(of_list_id))
+            .select(tags::all_columns)
+            .order((lower(tags::name).asc(), tags::id.asc()))
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading tags of list"))
+    }
+
+    /// put a tag of the user on one of the user's lists, tagging twice changes nothing
+    pub fn tag_list(&self, owner_id: i64, list_id: i64, tag_id: i64) -> Result<(), ApiError> {
+        use super::schema::{list_tags, lists, tags};
+
+        lists::table
+            .find(list_id)
+            .filter(lists::user_id.eq(owner_id))
+            .filter(lists::deleted_at.is_null())
+            .select(lists::id)
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+        tags::table
+            .find(tag_id)
+            .filter(tags::user_id.eq(owner_id))
+            .select(tags::id)
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading tag"))?;
+
+        diesel::insert_into(list_tags::table)
+            .values(&ListTag { list_id, tag_id })
+            .on_conflict_do_nothing()
+            .execute(&self.connection)
+            .map(|_| ())
+            .map_err(|err| ApiError::from_diesel_err(err, "while tagging list"))
+    }
+
+    /// take a tag off one of the user's lists
+    pub fn untag_list(
+        &self,
+        owner_id: i64,
+        of_list_id: i64,
+        of_tag_id: i64,
+    ) -> Result<usize, ApiError> {
+        use super::schema::list_tags::dsl::*;
+        use super::schema::tags;
+
+        let owned_tag = tags::table
+            .filter(tags::user_id.eq(owner_id))
+            .select(tags::id);
+        let deleted = diesel::delete(
+            list_tags
+                .filter(list_id.eq(of_list_id))
+                .filter(tag_id.eq(of_tag_id))
+                .filter(tag_id.eq_any(owned_tag)),
+        )
+        .execute(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while untagging list"))?;
+
+        if deleted == 0 {
+            return Err(ApiError::new("Tag not found on list", ErrorType::NotFound));
+        }
+        return Ok(deleted);
+    }
+
+    /// the templates of a user, by title
+    pub fn get_templates(&self, owner_id: i64) -> Result<Vec<List>, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        lists
+            .filter(user_id.eq(owner_id))
+            .filter(is_template.eq(true))
+            .filter(deleted_at.is_null())
+            .order((title.asc(), id.asc()))
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading templates"))
+    }
+
+    /// a template of the user that is not in the trash
+    fn get_owned_template(&self, owner_id: i64, template_id: i64) -> Result<List, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        lists
+            .find(template_id)
+            .filter(user_id.eq(owner_id))
+            .filter(is_template.eq(true))
+            .filter(deleted_at.is_null())
+            .first::<List>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading template"))
+    }
+
+    /// attach a recurrence to a template of the user, replacing the one it had
+    pub fn set_recurrence(
+        &self,
+        owner_id: i64,
+        dto: CreateRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        use super::schema::recurrences::dsl::*;
+
+        self.get_owned_template(owner_id, dto.template_id)?;
+
+        diesel::insert_into(recurrences)
+            .values(&dto)
+            .on_conflict(template_id)
+            .do_update()
+            .set(&dto)
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while saving recurrence"))
+    }
+
+    pub fn get_recurrence(
+        &self,
+        owner_id: i64,
+        of_template_id: i64,
+    ) -> Result<Recurrence, ApiError> {
+        use super::schema::recurrences::dsl::*;
+
+        self.get_owned_template(owner_id, of_template_id)?;
+
+        recurrences
+            .filter(template_id.eq(of_template_id))
+            .first(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading recurrence"))
+    }
+
+    pub fn delete_recurrence(&self, owner_id: i64, of_template_id: i64) -> Result<usize, ApiError> {
+        use super::schema::recurrences::dsl::*;
+
+        self.get_owned_template(owner_id, of_template_id)?;
+
+        let deleted = diesel::delete(recurrences.filter(template_id.eq(of_template_id)))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while deleting recurrence"))?;
+
+        if deleted == 0 {
+            return Err(ApiError::new("Recurrence not found", ErrorType::NotFound));
+        }
+        return Ok(deleted);
+    }
+
+    /// create the list for one due recurrence and advance it past `now`, in one transaction.
+    /// Recurrences locked by another instance are skipped, so each occurrence creates exactly one list,
+    /// occurrences missed while no instance was running are caught up with a single list.
+    /// Returns None once nothing is due, no list is created for templates in the trash.
+    /// If the list can not be created the occurrence is skipped and the error kept in `last_error`,
+    /// so one broken template does not hold up the others.
+    pub fn run_due_recurrence(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<(Recurrence, Option<List>)>, ApiError> {
+        use super::schema::lists;
+        use super::schema::recurrences::dsl::*;
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            let due = recurrences
+                .filter(next_run_at.le(now))
+                .order(next_run_at.asc())
+                .for_update()
+                .skip_locked()
+                .first::<Recurrence>(&self.connection)
+                .optional()
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading due recurrence"))?;
+            let due = match due {
+                Some(due) => due,
+                None => return Ok(None),
+            };
+
+            let occurrence = due.next_run_at.unwrap_or(now);
+            let next = due
+                .rule
+                .parse::<RecurrenceRule>()
+                .ok()
+                .and_then(|parsed| parsed.next_after(due.starts_at, now));
+
+            let template = lists::table
+                .find(due.template_id)
+                .first::<List>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading template"))?;
+            let created = if template.is_template && template.deleted_at.is_none() {
+                let options = CloneOptions {
+                    title: Some(format!(
+                        "{} {}",
+                        template.title,
+                        occurrence.format("%Y-%m-%d")
+                    )),
+                    reset_checked: true,
+                    as_template: false,
+                    from_template: true,
+                };
+                // clone_list runs in a savepoint, a failure only rolls back the list
+                Some(self.clone_list(template.id, template.user_id, options))
+            } else {
+                None
+            };
+
+            let advanced = match &created {
+                Some(Err(err)) => diesel::update(recurrences.find(due.id))
+                    .set((next_run_at.eq(next), last_error.eq(&err.message)))
+                    .get_result::<Recurrence>(&self.connection),
+                _ => diesel::update(recurrences.find(due.id))
+                    .set((
+                        next_run_at.eq(next),
+                        last_run_at.eq(occurrence),
+                        last_error.eq(None::<String>),
+                    ))
+                    .get_result::<Recurrence>(&self.connection),
+            }
+            .map_err(|err| ApiError::from_diesel_err(err, "while advancing recurrence"))?;
+
+            Ok(Some((advanced, created.and_then(Result::ok))))
+        })
+    }
+
+    /// copy a list of the user with all its items into a new list of the same user.
+    /// Only templates are accepted as source if `from_template` is set. Due dates and reminders are not copied.
+    /// A copy with a budget may start past a threshold, its owner is notified like for any other change.
+    pub fn clone_list(
+        &self,
+        source_id: i64,
+        owner_id: i64,
+        options: CloneOptions,
+    ) -> Result<List, ApiError> {
+        self.connection.transaction::<_, ApiError, _>(|| {
+            let list = self
+                .copy_list(source_id, owner_id, options)
+                .map_err(|err| ApiError::from_diesel_err(err, "while cloning list"))?;
+            self.check_budget(list.id)?;
+            Ok(list)
+        })
+    }
+
+    fn copy_list(
+        &self,
+        source_id: i64,
+        owner_id: i64,
+        options: CloneOptions,
+    ) -> Result<List, diesel::result::Error> {
+        use super::schema::items;
+        use super::schema::lists;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let mut source = lists::table
+                    .find(source_id)
+                    .filter(lists::user_id.eq(owner_id))
+                    .filter(lists::deleted_at.is_null())
+                    .into_boxed();
+                if options.from_template {
+                    source = source.filter(lists::is_template.eq(true));
+                }
+                let source = source.first::<List>(&self.connection)?;
+
+                let list = diesel::insert_into(lists::table)
+                    .values(&CreateList {
+                        user_id: owner_id,
+                        title: options.title.clone().unwrap_or(source.title),
+                        subtitle: source.subtitle,
+                        is_template: options.as_template,
+                        budget_amount: source.budget_amount,
+                        budget_currency: source.budget_currency,
+                    })
+                    .get_result::<List>(&self.connection)?;
+
+                let source_items = items::table
+                    .filter(items::list_id.eq(source_id))
+                    .filter(items::deleted_at.is_null())
+                    .order((items::position.asc(), items::id.asc()))
+                    .load::<Item>(&self.connection)?;
+
+                // one insert per item, so every copy is known to belong to its source
+                let mut copy_of: HashMap<i64, i64> = HashMap::new();
+                let mut source_parents: Vec<(i64, Option<i64>)> = Vec::new();
+                for item in source_items {
+                    let keep_checked = item.checked && !options.reset_checked;
+                    let copy_id = diesel::insert_into(items::table)
+                        .values((
+                            items::list_id.eq(list.id),
+                            items::title.eq(item.title),
+                            items::quantity.eq(item.quantity),
+                            items::unit.eq(item.unit),
+                            items::position.eq(item.position),
+                            items::checked.eq(keep_checked),
+                            items::checked_at.eq(item.checked_at.filter(|_| keep_checked)),
+                            items::checked_by.eq(item.checked_by.filter(|_| keep_checked)),
+                            items::notes.eq(item.notes),
+                            items::url.eq(item.url),
+                            items::unit_price.eq(item.unit_price),
+                            items::currency.eq(item.currency),
+                        ))
+                        .returning(items::id)
+                        .get_result::<i64>(&self.connection)?;
+                    copy_of.insert(item.id, copy_id);
+                    source_parents.push((item.id, item.parent_item_id));
+                }
+
+                // nest the copies like their sources once they all exist
+                for (source_item_id, source_parent_id) in source_parents {
+                    if let Some(copy_parent_id) = source_parent_id.and_then(|p| copy_of.get(&p)) {
+                        diesel::update(items::table.find(copy_of[&source_item_id]))
+                            .set(items::parent_item_id.eq(copy_parent_id))
+                            .execute(&self.connection)?;
+                    }
+                }
+
+                Ok(list)
+            })
+    }
+
+    /// retrieve a page of a user's lists ordered by id, starting after the given id,
+    /// including those in the trash
+    pub fn get_user_lists_page(
+        &self,
+        owner_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<List>, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        lists
+            .filter(user_id.eq(owner_id))
+            .filter(id.gt(after_id))
+            .order(id.asc())
+            .limit(limit)
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading user lists"))
+    }
+
+    /// retrieve the items of all the given lists in one query, including those in the trash
+    pub fn get_items_of_lists(&self, of_lists: &[List]) -> Result<Vec<Item>, ApiError> {
+        use super::schema::items::dsl::*;
+
+        Item::belonging_to(of_lists)
+            .order((list_id.asc(), position.asc(), id.asc()))
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading items of lists"))
+    }
+
+    /// retrieve the stored revisions of the given lists in order of their id, starting after the given id
+    pub fn get_revisions_page(
+        &self,
+        of_list_ids: &[i64],
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<StoredRevision>, ApiError> {
+        use super::schema::list_revisions::dsl::*;
+
+        list_revisions
+            .filter(list_id.eq_any(of_list_ids))
+            .filter(id.gt(after_id))
+            .order(id.asc())
+            .limit(limit)
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading revisions of lists"))
+    }
+
+    /// retrieve the queued notifications of the user in order of their id, starting after the given id
+    pub fn get_user_notifications_page(
+        &self,
+        owner_id: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<PendingNotification>, ApiError> {
+        use super::schema::notifications::dsl::*;
+
+        notifications
+            .filter(user_id.eq(owner_id))
+            .filter(id.gt(after_id))
+            .order(id.asc())
+            .limit(limit)
+            .load(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading user notifications"))
+    }
+
+    /// retrieve the items of all the given lists in one query, grouped per list in the same order
+    pub fn get_items_grouped(&self, of_lists: &[List]) -> Result<Vec<Vec<Item>>, ApiError> {
+        use super::schema::items::dsl::*;
+
+        Item::belonging_to(of_lists)
+            .filter(deleted_at.is_null())
+            .order((list_id.asc(), position.asc(), id.asc()))
+            .load::<Item>(&self.connection)
+            .map(|loaded| loaded.grouped_by(of_lists))
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading items of lists"))
+    }
+
+    /// full text search over the titles and subtitles of a user's lists and the titles of their items,
+    /// best matches first. Snippets are HTML: the text is escaped, then the matches are marked.
+    pub fn search(
+        &self,
+        owner_id: i64,
+        text: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, ApiError> {
+        use diesel::sql_types::{BigInt, Text};
+
+        diesel::sql_query(
+            "WITH search AS (
+                SELECT plainto_tsquery(retrolist_search_language(), $2) AS query
+            )
+            SELECT 'list' AS kind, lists.id AS id, lists.id AS list_id, lists.title AS list_title,
+                ts_rank(lists.search_vector, search.query) AS rank,
+                ts_headline(retrolist_search_language(),
+                    retrolist_escape_html(lists.title || ' ' || lists.subtitle),
+                    search.query, 'StartSel=<mark>, StopSel=</mark>') AS snippet
+            FROM lists, search
+            WHERE lists.user_id = $1 AND lists.deleted_at IS NULL
+                AND lists.search_vector @@ search.query
+            UNION ALL
+            SELECT 'item' AS kind, items.id AS id, lists.id AS list_id, lists.title AS list_title,
+                ts_rank(items.search_vector, search.query) AS rank,
+                ts_headline(retrolist_search_language(), retrolist_escape_html(items.title),
+                    search.query, 'StartSel=<mark>, StopSel=</mark>') AS snippet
+            FROM items JOIN lists ON lists.id = items.list_id, search
+            WHERE lists.user_id = $1 AND lists.deleted_at IS NULL AND items.deleted_at IS NULL
+                AND items.search_vector @@ search.query
+            ORDER BY rank DESC, id ASC
+            LIMIT $3",
+        )
+        .bind::<BigInt, _>(owner_id)
+        .bind::<Text, _>(text)
+        .bind::<BigInt, _>(limit)
+        .load::<SearchHit>(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while searching"))
+    }
+
     /// retrieve one list from the db, complete with the the items
     pub fn get_list(&self, list_id: i64) -> Result<(List, Vec<Item>), ApiError> {
         use super::schema::lists::dsl::*;
 
-        match lists.find(list_id).first::<List>(&self.connection) {
-            Ok(list) => match Item::belonging_to(&list).load::<Item>(&self.connection) {
+        match lists
+            .find(list_id)
+            .filter(deleted_at.is_null())
+            .first::<List>(&self.connection)
+        {
+            Ok(list) => match Item::belonging_to(&list)
+                .filter(super::schema::items::deleted_at.is_null())
+                .order((
+                    super::schema::items::position.asc(),
+                    super::schema::items::id.asc(),
+                ))
+                .load::<Item>(&self.connection)
+            {
                 Ok(items) => return Ok((list, items)),
                 Err(_) => return Ok((list, vec![])),
             },
@@ -89,6 +869,7 @@ impl DBManager {
 
         let updated = diesel::update(lists)
             .filter(id.eq(list_id))
+            .filter(deleted_at.is_null())
             .set((title.eq(new_title), subtitle.eq(new_subtitle)))
             .execute(&self.connection)
             .map_err(|err| ApiError::from_diesel_err(err, "while updating list"))?;
@@ -99,10 +880,12 @@ impl DBManager {
         return Ok(updated);
     }
 
+    /// move a list to the trash, its items are hidden along with it
     pub fn delete_list(&self, list_id: i64) -> Result<usize, ApiError> {
         use super::schema::lists::dsl::*;
 
-        let deleted = diesel::delete(lists.filter(id.eq(list_id)))
+        let deleted = diesel::update(lists.filter(id.eq(list_id)).filter(deleted_at.is_null()))
+            .set(deleted_at.eq(Utc::now()))
             .execute(&self.connection)
             .map_err(|err| ApiError::from_diesel_err(err, "while deleting list"))?;
 
@@ -112,29 +895,471 @@ impl DBManager {
         return Ok(deleted);
     }
 
-    pub fn create_item(&self, dto: CreateItem) -> Result<Item, ApiError> {
+    /// insert an item at the given index of its list, or at the end
+    pub fn create_item(&self, dto: CreateItem, at_index: Option<usize>) -> Result<Item, ApiError> {
         use super::schema::items;
 
-        diesel::insert_into(items::table) // insert into items table
-            .values(&dto) // use values from CreateListDTO
-            .get_result(&self.connection) // execute query
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let mut positions = items::table
+                    .filter(items::list_id.eq(dto.list_id))
+                    .filter(items::deleted_at.is_null())
+                    .order((items::position.asc(), items::id.asc()))
+                    .select(items::position)
+                    .for_update()
+                    .load::<i64>(&self.connection)?;
+
+                let index = at_index.unwrap_or(positions.len());
+                let new_position = match position_at(&positions, index) {
+                    Some(new_position) => new_position,
+                    None => {
+                        // neighbours are adjacent, spread the list out again
+                        positions = self.renumber_items(dto.list_id)?;
+                        position_at(&positions, index).unwrap_or(POSITION_GAP)
+                    }
+                };
+
+                diesel::insert_into(items::table) // insert into items table
+                    .values((&dto, items::position.eq(new_position))) // use values from CreateItem
+                    .get_result(&self.connection) // execute query
+            })
             .map_err(|err| ApiError::from_diesel_err(err, "while creating item"))
         // if error occurred map it to ApiError
     }
 
+    /// add the item to its list. An unchecked item that looks like the same product is, depending on the policy,
+    /// merged into if the units and details are compatible, returned as duplicate instead of adding, or ignored.
+    /// Merging fills in the details the existing item lacks, the item is added on its own if they differ.
+    pub fn add_item(
+        &self,
+        dto: CreateItem,
+        at_index: Option<usize>,
+        policy: DuplicatePolicy,
+    ) -> Result<AddedItem, ApiError> {
+        self.connection.transaction::<_, ApiError, _>(|| {
+            self.get_live_list_id(dto.list_id)?;
+            if let Some(new_parent_id) = dto.parent_item_id {
+                self.check_parent(dto.list_id, new_parent_id, None)?;
+            }
+
+            let candidates = match policy.on_duplicate {
+                OnDuplicate::Allow => vec![],
+                _ => self.find_duplicates(&dto, &policy).map_err(|err| {
+                    ApiError::from_diesel_err(err, "while looking for duplicates")
+                })?,
+            };
+
+            if policy.on_duplicate == OnDuplicate::Reject {
+                if let Some(duplicate) = candidates.into_iter().next() {
+                    return Ok(AddedItem::Duplicate(duplicate));
+                }
+            } else {
+                for candidate in candidates {
+                    let converted = dto.unit.convert(&dto.quantity, candidate.unit);
+                    let details = dto.details.merged_into(dto.unit, &candidate);
+                    if let (Some(converted), Some(details)) = (converted, details) {
+                        if &candidate.quantity + &converted >= max_quantity() {
+                            return Err(ApiError::new(
+                                format!(
+                                    "quantity: merged with item {} it must be less than {}",
+                                    candidate.id,
+                                    max_quantity()
+                                )
+                                .as_str(),
+                                ErrorType::BadRequest,
+                            ));
+                        }
+                        return self
+                            .merge_quantity(candidate, converted, details)
+                            .map(AddedItem::Merged)
+                            .map_err(|err| ApiError::from_diesel_err(err, "while merging item"));
+                    }
+                }
+            }
+
+            self.create_item(dto, at_index).map(AddedItem::Created)
+        })
+    }
+
+    /// unchecked items under the same parent with a title similar enough to the new one, most similar first
+    fn find_duplicates(
+        &self,
+        dto: &CreateItem,
+        policy: &DuplicatePolicy,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        let mut candidates: Vec<(f64, Item)> = items
+            .filter(list_id.eq(dto.list_id))
+            .filter(checked.eq(false))
+            .filter(deleted_at.is_null())
+            .order((position.asc(), id.asc()))
+            .for_update()
+            .load::<Item>(&self.connection)?
+            .into_iter()
+            .filter(|item| item.parent_item_id == dto.parent_item_id)
+            .map(|item| (title_similarity(&dto.title, &item.title), item))
+            .filter(|(score, _)| *score >= policy.threshold)
+            .collect();
+
+        // stable, so equally similar items stay in list order
+        candidates.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(candidates.into_iter().map(|(_, item)| item).collect())
+    }
+
+    /// add a quantity, already converted to the item's unit, to an item and replace its details
+    fn merge_quantity(
+        &self,
+        item: Item,
+        converted: BigDecimal,
+        details: ItemDetails,
+    ) -> Result<Item, diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        diesel::update(items.find(item.id))
+            .set((quantity.eq(item.quantity + converted), &details))
+            .get_result(&self.connection)
+    }
+
+    /// make sure an item can be nested under the parent: a live item of the same list that is neither
+    /// the nested item nor one of its sub-items, and the nesting does not get too deep
+    fn check_parent(
+        &self,
+        of_list_id: i64,
+        new_parent_id: i64,
+        nested_item_id: Option<i64>,
+    ) -> Result<(), ApiError> {
+        use super::schema::items::dsl::*;
+
+        let invalid = |message: &str| {
+            ApiError::new(
+                format!("parent_item_id: {}", message).as_str(),
+                ErrorType::BadRequest,
+            )
+        };
+        let too_deep = || {
+            invalid(format!("items can be nested at most {} levels deep", MAX_ITEM_DEPTH).as_str())
+        };
+
+        // walk up from the new parent, counting the levels above the nested item
+        let mut depth = 0;
+        let mut ancestor_id = Some(new_parent_id);
+        while let Some(current_id) = ancestor_id {
+            if Some(current_id) == nested_item_id {
+                return Err(invalid(
+                    "an item can not be nested under itself or its sub-items",
+                ));
+            }
+            if depth >= MAX_ITEM_DEPTH {
+                return Err(too_deep());
+            }
+            let (ancestor_list_id, next_id) = items
+                .find(current_id)
+                .filter(deleted_at.is_null())
+                .select((list_id, parent_item_id))
+                .first::<(i64, Option<i64>)>(&self.connection)
+                .optional()
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading parent item"))?
+                .ok_or_else(|| invalid("must be an item of the same list"))?;
+            if ancestor_list_id != of_list_id {
+                return Err(invalid("must be an item of the same list"));
+            }
+            depth += 1;
+            ancestor_id = next_id;
+        }
+
+        let nested_levels = match nested_item_id {
+            Some(nested_id) => self
+                .get_descendant_levels(&[nested_id], None)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading sub-items"))?
+                .len(),
+            None => 0,
+        };
+        if depth + 1 + nested_levels > MAX_ITEM_DEPTH {
+            return Err(too_deep());
+        }
+        Ok(())
+    }
+
+    /// ids of the sub-items of the items level by level, the items themselves left out.
+    /// Only live sub-items without `trashed_at`, otherwise those moved to the trash at that time.
+    fn get_descendant_levels(
+        &self,
+        item_ids: &[i64],
+        trashed_at: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Vec<i64>>, diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        let mut levels = Vec::new();
+        let mut current_ids = item_ids.to_vec();
+        // nesting is limited, the bound only guards against broken data
+        while !current_ids.is_empty() && levels.len() < MAX_ITEM_DEPTH {
+            let mut query = items
+                .filter(parent_item_id.eq_any(&current_ids))
+                .select(id)
+                .into_boxed();
+            query = match trashed_at {
+                Some(trashed) => query.filter(deleted_at.eq(trashed)),
+                None => query.filter(deleted_at.is_null()),
+            };
+            current_ids = query.load::<i64>(&self.connection)?;
+            if !current_ids.is_empty() {
+                levels.push(current_ids.clone());
+            }
+        }
+        Ok(levels)
+    }
+
+    /// the items together with all their live sub-items
+    fn with_descendants(&self, item_ids: &[i64]) -> Result<Vec<i64>, diesel::result::Error> {
+        let mut all_ids = item_ids.to_vec();
+        all_ids.extend(
+            self.get_descendant_levels(item_ids, None)?
+                .into_iter()
+                .flatten(),
+        );
+        Ok(all_ids)
+    }
+
+    /// nest an item under another item of its list, or move it to the top level without a parent
+    pub fn set_item_parent(
+        &self,
+        item_id: i64,
+        new_parent_id: Option<i64>,
+    ) -> Result<Item, ApiError> {
+        use super::schema::items::dsl::*;
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            let item = items
+                .find(item_id)
+                .filter(deleted_at.is_null())
+                .filter(list_id.eq_any(live_list_ids()))
+                .for_update()
+                .first::<Item>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading item"))?;
+
+            if let Some(parent_id) = new_parent_id {
+                self.check_parent(item.list_id, parent_id, Some(item_id))?;
+            }
+
+            diesel::update(items.find(item_id))
+                .set(parent_item_id.eq(new_parent_id))
+                .get_result(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while nesting item"))
+        })
+    }
+
+    /// set or clear the due date and reminder of an item, a changed reminder will be sent again
+    pub fn set_item_schedule(
+        &self,
+        item_id: i64,
+        new_due_at: Option<DateTime<Utc>>,
+        new_remind_at: Option<DateTime<Utc>>,
+    ) -> Result<Item, ApiError> {
+        use super::schema::items::dsl::*;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let item = items
+                    .find(item_id)
+                    .filter(deleted_at.is_null())
+                    .filter(list_id.eq_any(live_list_ids()))
+                    .for_update()
+                    .first::<Item>(&self.connection)?;
+
+                let new_reminded_at = if item.remind_at == new_remind_at {
+                    item.reminded_at
+                } else {
+                    None
+                };
+
+                diesel::update(items.find(item_id))
+                    .set((
+                        due_at.eq(new_due_at),
+                        remind_at.eq(new_remind_at),
+                        reminded_at.eq(new_reminded_at),
+                    ))
+                    .get_result(&self.connection)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while scheduling item"))
+    }
+
+    /// replace the notes, link and price of an item
+    pub fn set_item_details(&self, item_id: i64, details: ItemDetails) -> Result<Item, ApiError> {
+        use super::schema::items::dsl::*;
+
+        diesel::update(
+            items
+                .find(item_id)
+                .filter(deleted_at.is_null())
+                .filter(list_id.eq_any(live_list_ids())),
+        )
+        .set(&details)
+        .get_result(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while updating item details"))
+    }
+
+    /// unchecked items of the user's lists due before the given time with their lists, earliest first.
+    /// Items of templates are left out.
+    pub fn get_due_items(
+        &self,
+        owner_id: i64,
+        due_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(Item, List)>, ApiError> {
+        use super::schema::{items, lists};
+
+        items::table
+            .inner_join(lists::table)
+            .filter(lists::user_id.eq(owner_id))
+            .filter(lists::deleted_at.is_null())
+            .filter(lists::is_template.eq(false))
+            .filter(items::deleted_at.is_null())
+            .filter(items::checked.eq(false))
+            .filter(items::due_at.lt(due_before))
+            .order((items::due_at.asc(), items::id.asc()))
+            .limit(limit)
+            .load::<(Item, List)>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading due items"))
+    }
+
+    /// mark unchecked items whose reminder time has come as reminded and return them with their lists.
+    /// Safe to run on several instances at once, each reminder is claimed by exactly one of them.
+    pub fn claim_due_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(Item, List)>, ApiError> {
+        use super::schema::{items, lists};
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let due = items::table
+                    .inner_join(lists::table)
+                    .filter(items::remind_at.le(now))
+                    .filter(items::reminded_at.is_null())
+                    .filter(items::deleted_at.is_null())
+                    .filter(items::checked.eq(false))
+                    .filter(lists::deleted_at.is_null())
+                    .filter(lists::is_template.eq(false))
+                    .order((items::remind_at.asc(), items::id.asc()))
+                    .limit(limit)
+                    .for_update()
+                    .skip_locked()
+                    .load::<(Item, List)>(&self.connection)?;
+
+                let claimed_ids: Vec<i64> = due.iter().map(|(item, _)| item.id).collect();
+                diesel::update(items::table.filter(items::id.eq_any(&claimed_ids)))
+                    .set(items::reminded_at.eq(now))
+                    .execute(&self.connection)?;
+                Ok(due)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while claiming due reminders"))
+    }
+
+    /// hand a claimed reminder back so it is sent again, e.g. after delivery failed
+    pub fn release_reminder(&self, item_id: i64) -> Result<usize, ApiError> {
+        use super::schema::items::dsl::*;
+
+        diesel::update(items.find(item_id))
+            .set(reminded_at.eq(None::<DateTime<Utc>>))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while releasing reminder"))
+    }
+
+    /// reset the positions of a list's items to evenly spaced values, keeping their order
+    fn renumber_items(&self, of_list_id: i64) -> Result<Vec<i64>, diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        let item_ids = items
+            .filter(list_id.eq(of_list_id))
+            .filter(deleted_at.is_null())
+            .order((position.asc(), id.asc()))
+            .select(id)
+            .load::<i64>(&self.connection)?;
+
+        self.set_item_positions(&item_ids)
+    }
+
+    /// give the items the evenly spaced positions matching their order in the slice
+    fn set_item_positions(&self, item_ids: &[i64]) -> Result<Vec<i64>, diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        let mut positions = Vec::with_capacity(item_ids.len());
+        for (index, item_id) in item_ids.iter().enumerate() {
+            let new_position = (index as i64 + 1) * POSITION_GAP;
+            diesel::update(items.find(item_id))
+                .set(position.eq(new_position))
+                .execute(&self.connection)?;
+            positions.push(new_position);
+        }
+        Ok(positions)
+    }
+
+    /// put all items of a list into the given order, the ids must be exactly the list's items
+    pub fn reorder_items(&self, of_list_id: i64, item_ids: Vec<i64>) -> Result<(), ApiError> {
+        use super::schema::items::dsl::*;
+        use super::schema::lists;
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            lists::table
+                .find(of_list_id)
+                .filter(lists::deleted_at.is_null())
+                .select(lists::id)
+                .for_update()
+                .first::<i64>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+
+            let mut current_ids = items
+                .filter(list_id.eq(of_list_id))
+                .filter(deleted_at.is_null())
+                .select(id)
+                .load::<i64>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading items"))?;
+            current_ids.sort();
+
+            let mut requested_ids = item_ids.clone();
+            requested_ids.sort();
+            if requested_ids != current_ids {
+                return Err(ApiError::new(
+                    "item_ids must contain every item of the list exactly once",
+                    ErrorType::BadRequest,
+                ));
+            }
+
+            self.set_item_positions(&item_ids)
+                .map_err(|err| ApiError::from_diesel_err(err, "while reordering items"))?;
+            Ok(())
+        })
+    }
+
     pub fn update_item(
         &self,
         item_id: i64,
         new_title: String,
-        new_amount: i32,
+        new_quantity: BigDecimal,
+        new_unit: Option<Unit>,
     ) -> Result<usize, ApiError> {
         use super::schema::items::dsl::*;
 
-        let updated = diesel::update(items)
+        let target = items
             .filter(id.eq(item_id))
-            .set((title.eq(new_title), amount.eq(new_amount)))
-            .execute(&self.connection)
-            .map_err(|err| ApiError::from_diesel_err(err, "while updating item"))?;
+            .filter(deleted_at.is_null())
+            .filter(list_id.eq_any(live_list_ids()));
+        // without a unit the stored one is kept
+        let updated = match new_unit {
+            Some(new_unit) => diesel::update(target)
+                .set((
+                    title.eq(new_title),
+                    quantity.eq(new_quantity),
+                    unit.eq(new_unit),
+                ))
+                .execute(&self.connection),
+            None => diesel::update(target)
+                .set((title.eq(new_title), quantity.eq(new_quantity)))
+                .execute(&self.connection),
+        }
+        .map_err(|err| ApiError::from_diesel_err(err, "while updating item"))?;
 
         if updated == 0 {
             return Err(ApiError::new("Item not found", ErrorType::NotFound));
@@ -142,11 +1367,125 @@ impl DBManager {
         return Ok(updated);
     }
 
+    /// flip the checked state of an item, remembering who checked it and when.
+    /// With `cascade` its sub-items are given the same state.
+    pub fn toggle_item(
+        &self,
+        item_id: i64,
+        toggled_by: i64,
+        cascade: bool,
+    ) -> Result<Item, ApiError> {
+        use super::schema::items::dsl::*;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let item = items
+                    .find(item_id)
+                    .filter(deleted_at.is_null())
+                    .filter(list_id.eq_any(live_list_ids()))
+                    .for_update()
+                    .first::<Item>(&self.connection)?;
+
+                let now_checked = !item.checked;
+                let (new_checked_at, new_checked_by) = if now_checked {
+                    (Some(Utc::now()), Some(toggled_by))
+                } else {
+                    (None, None)
+                };
+
+                if cascade {
+                    let descendant_ids = self.with_descendants(&[item_id])?;
+                    diesel::update(
+                        items
+                            .filter(id.eq_any(&descendant_ids))
+                            .filter(id.ne(item_id))
+                            .filter(checked.ne(now_checked)),
+                    )
+                    .set((
+                        checked.eq(now_checked),
+                        checked_at.eq(new_checked_at),
+                        checked_by.eq(new_checked_by),
+                    ))
+                    .execute(&self.connection)?;
+                }
+
+                diesel::update(items.find(item_id))
+                    .set((
+                        checked.eq(now_checked),
+                        checked_at.eq(new_checked_at),
+                        checked_by.eq(new_checked_by),
+                    ))
+                    .get_result(&self.connection)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while toggling item"))
+    }
+
+    /// move all checked items of a list to the trash, sub-items go along with them
+    pub fn clear_checked_items(&self, of_list_id: i64) -> Result<usize, ApiError> {
+        use super::schema::items::dsl::*;
+
+        self.get_live_list_id(of_list_id)?;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let checked_ids = items
+                    .filter(list_id.eq(of_list_id))
+                    .filter(checked.eq(true))
+                    .filter(deleted_at.is_null())
+                    .select(id)
+                    .load::<i64>(&self.connection)?;
+                let cleared_ids = self.with_descendants(&checked_ids)?;
+
+                diesel::update(
+                    items
+                        .filter(id.eq_any(&cleared_ids))
+                        .filter(deleted_at.is_null()),
+                )
+                .set(deleted_at.eq(Utc::now()))
+                .execute(&self.connection)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while clearing checked items"))
+    }
+
+    /// count checked and total items for each of the given lists
+    pub fn get_progress(&self, of_list_ids: Vec<i64>) -> Result<HashMap<i64, Progress>, ApiError> {
+        use super::schema::items::dsl::*;
+
+        let states = items
+            .filter(list_id.eq_any(of_list_ids))
+            .filter(deleted_at.is_null())
+            .select((list_id, checked))
+            .load::<(i64, bool)>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while counting checked items"))?;
+
+        let mut progress: HashMap<i64, Progress> = HashMap::new();
+        for (item_list_id, item_checked) in states {
+            let list_progress = progress.entry(item_list_id).or_default();
+            list_progress.total += 1;
+            if item_checked {
+                list_progress.checked += 1;
+            }
+        }
+        Ok(progress)
+    }
+
+    /// move an item with its sub-items to the trash
     pub fn delete_item(&self, item_id: i64) -> Result<usize, ApiError> {
         use super::schema::items::dsl::*;
 
-        let deleted = diesel::delete(items.filter(id.eq(item_id)))
-            .execute(&self.connection)
+        let deleted = self
+            .connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let deleted_ids = self.with_descendants(&[item_id])?;
+                diesel::update(
+                    items
+                        .filter(id.eq_any(&deleted_ids))
+                        .filter(deleted_at.is_null())
+                        .filter(list_id.eq_any(live_list_ids())),
+                )
+                .set(deleted_at.eq(Utc::now()))
+                .execute(&self.connection)
+            })
             .map_err(|err| ApiError::from_diesel_err(err, "while deleting item"))?;
 
         if deleted == 0 {
@@ -154,4 +1493,708 @@ impl DBManager {
         }
         return Ok(deleted);
     }
+
+    /// make sure the list exists and is not in the trash
+    fn get_live_list_id(&self, list_id: i64) -> Result<i64, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        lists
+            .find(list_id)
+            .filter(deleted_at.is_null())
+            .select(id)
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))
+    }
+
+    /// the user's lists in the trash and the trashed items of their other lists, latest first.
+    /// Items of a trashed list are not listed separately, they come back with the list.
+    pub fn get_trash(&self, owner_id: i64) -> Result<(Vec<List>, Vec<Item>), ApiError> {
+        use super::schema::{items, lists};
+
+        let trashed_lists = lists::table
+            .filter(lists::user_id.eq(owner_id))
+            .filter(lists::deleted_at.is_not_null())
+            .order((lists::deleted_at.desc(), lists::id.desc()))
+            .load::<List>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading trashed lists"))?;
+
+        let trashed_items = items::table
+            .inner_join(lists::table)
+            .filter(lists::user_id.eq(owner_id))
+            .filter(lists::deleted_at.is_null())
+            .filter(items::deleted_at.is_not_null())
+            .order((items::deleted_at.desc(), items::id.desc()))
+            .select(items::all_columns)
+            .load::<Item>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading trashed items"))?;
+
+        Ok((trashed_lists, trashed_items))
+    }
+
+    /// take a list of the user out of the trash
+    pub fn restore_list(&self, owner_id: i64, list_id: i64) -> Result<List, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        diesel::update(
+            lists
+                .find(list_id)
+                .filter(user_id.eq(owner_id))
+                .filter(deleted_at.is_not_null()),
+        )
+        .set(deleted_at.eq(None::<DateTime<Utc>>))
+        .get_result(&self.connection)
+        .map_err(|err| ApiError::from_diesel_err(err, "while restoring list"))
+    }
+
+    /// take an item of the user out of the trash together with the sub-items trashed along with it.
+    /// A trashed list or parent item has to be restored first.
+    pub fn restore_item(&self, owner_id: i64, item_id: i64) -> Result<Item, ApiError> {
+        use super::schema::{items, lists};
+
+        let (item, list) = items::table
+            .inner_join(lists::table)
+            .filter(items::id.eq(item_id))
+            .filter(lists::user_id.eq(owner_id))
+            .filter(items::deleted_at.is_not_null())
+            .select((items::all_columns, lists::all_columns))
+            .first::<(Item, List)>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading trashed item"))?;
+
+        if list.deleted_at.is_some() {
+            return Err(ApiError::new(
+                "The list of this item is in the trash, restore the list first",
+                ErrorType::Conflict,
+            ));
+        }
+        if let Some(of_parent_id) = item.parent_item_id {
+            let parent_trashed = items::table
+                .find(of_parent_id)
+                .select(items::deleted_at.is_not_null())
+                .first::<bool>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading parent item"))?;
+            if parent_trashed {
+                return Err(ApiError::new(
+                    "The parent of this item is in the trash, restore the parent first",
+                    ErrorType::Conflict,
+                ));
+            }
+        }
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let restored_ids: Vec<i64> = self
+                    .get_descendant_levels(&[item_id], item.deleted_at)?
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                diesel::update(items::table.filter(items::id.eq_any(&restored_ids)))
+                    .set(items::deleted_at.eq(None::<DateTime<Utc>>))
+                    .execute(&self.connection)?;
+
+                diesel::update(items::table.find(item_id))
+                    .set(items::deleted_at.eq(None::<DateTime<Utc>>))
+                    .get_result(&self.connection)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while restoring item"))
+    }
+
+    /// permanently delete lists and items that were moved to the trash before the cutoff,
+    /// items of purged lists are removed by the cascading foreign key.
+    /// The budgets of the remaining lists that lost items are checked again.
+    pub fn purge_trash(&self, deleted_before: DateTime<Utc>) -> Result<(usize, usize), ApiError> {
+        use super::schema::{items, lists};
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            let purged_lists =
+                diesel::delete(lists::table.filter(lists::deleted_at.lt(deleted_before)))
+                    .execute(&self.connection)
+                    .map_err(|err| ApiError::from_diesel_err(err, "while purging trash"))?;
+            let purged_list_ids =
+                diesel::delete(items::table.filter(items::deleted_at.lt(deleted_before)))
+                    .returning(items::list_id)
+                    .get_results::<i64>(&self.connection)
+                    .map_err(|err| ApiError::from_diesel_err(err, "while purging trash"))?;
+
+            let mut list_ids = purged_list_ids.clone();
+            list_ids.sort();
+            list_ids.dedup();
+            for list_id in list_ids {
+                self.check_budget(list_id)?;
+            }
+            Ok((purged_lists, purged_list_ids.len()))
+        })
+    }
+
+    /// the list an item belongs to, also for items in the trash
+    pub fn get_item_list_id(&self, item_id: i64) -> Result<i64, ApiError> {
+        use super::schema::items::dsl::*;
+
+        items
+            .find(item_id)
+            .select(list_id)
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading item"))
+    }
+
+    /// the distinct lists the items belong to
+    pub fn get_items_list_ids(&self, item_ids: &[i64]) -> Result<Vec<i64>, ApiError> {
+        use super::schema::items::dsl::*;
+
+        items
+            .filter(id.eq_any(item_ids))
+            .select(list_id)
+            .distinct()
+            .load::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading items"))
+    }
+
+    /// move or copy items of the user to another list of the user, inserted in the given order
+    /// at the index of the target list or at its end. Copies start unchecked and without due date or reminder.
+    /// Moved items take their sub-items along and land at the top level of the target, copies leave them behind.
+    pub fn transfer_items(
+        &self,
+        owner_id: i64,
+        item_ids: &[i64],
+        target_list_id: i64,
+        at_index: Option<usize>,
+        copy: bool,
+    ) -> Result<Vec<Item>, ApiError> {
+        use super::schema::items;
+        use super::schema::lists;
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            lists::table
+                .find(target_list_id)
+                .filter(lists::user_id.eq(owner_id))
+                .filter(lists::deleted_at.is_null())
+                .select(lists::id)
+                .for_update()
+                .first::<i64>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading target list"))?;
+
+            let sources = items::table
+                .inner_join(lists::table)
+                .filter(items::id.eq_any(item_ids))
+                .filter(items::deleted_at.is_null())
+                .filter(lists::user_id.eq(owner_id))
+                .filter(lists::deleted_at.is_null())
+                .select(items::all_columns)
+                .for_update()
+                .load::<Item>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading items"))?;
+
+            // keep the order of the request, every item has to be found
+            let mut ordered = Vec::with_capacity(item_ids.len());
+            for item_id in item_ids {
+                match sources.iter().find(|item| item.id == *item_id) {
+                    Some(item) if !ordered.iter().any(|known: &Item| known.id == item.id) => {
+                        ordered.push(item.clone())
+                    }
+                    Some(_) => (),
+                    None => return Err(ApiError::new("Item not found", ErrorType::NotFound)),
+                }
+            }
+
+            let transferred_ids = if copy {
+                let copies: Vec<_> = ordered
+                    .iter()
+                    .map(|item| {
+                        (
+                            items::list_id.eq(target_list_id),
+                            items::title.eq(&item.title),
+                            items::quantity.eq(&item.quantity),
+                            items::unit.eq(item.unit),
+                            items::notes.eq(&item.notes),
+                            items::url.eq(&item.url),
+                            items::unit_price.eq(&item.unit_price),
+                            items::currency.eq(&item.currency),
+                        )
+                    })
+                    .collect();
+                diesel::insert_into(items::table)
+                    .values(&copies)
+                    .returning(items::id)
+                    .get_results::<i64>(&self.connection)
+            } else {
+                self.move_items(&ordered, target_list_id)
+            }
+            .map_err(|err| ApiError::from_diesel_err(err, "while transferring items"))?;
+
+            // place the transferred items as a block, the other items keep their order
+            let mut order = items::table
+                .filter(items::list_id.eq(target_list_id))
+                .filter(items::deleted_at.is_null())
+                .filter(diesel::dsl::not(items::id.eq_any(&transferred_ids)))
+                .order((items::position.asc(), items::id.asc()))
+                .select(items::id)
+                .load::<i64>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading target items"))?;
+            let index = at_index.unwrap_or(order.len()).min(order.len());
+            order.splice(index..index, transferred_ids.iter().cloned());
+            self.set_item_positions(&order)
+                .map_err(|err| ApiError::from_diesel_err(err, "while positioning items"))?;
+
+            items::table
+                .filter(items::id.eq_any(&transferred_ids))
+                .order((items::position.asc(), items::id.asc()))
+                .load::<Item>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading items"))
+        })
+    }
+
+    /// move the items with their sub-items to another list, sub-items stay nested.
+    /// Items whose parent is not moved along end up at the top level.
+    /// Returns the moved ids, each item in the given order followed by its sub-items in list order.
+    fn move_items(
+        &self,
+        moved: &[Item],
+        target_list_id: i64,
+    ) -> Result<Vec<i64>, diesel::result::Error> {
+        use super::schema::items;
+
+        let requested_ids: Vec<i64> = moved.iter().map(|item| item.id).collect();
+        let descendant_ids = self.with_descendants(&requested_ids)?;
+        let mut moved_ids = requested_ids.clone();
+        moved_ids.extend(
+            items::table
+                .filter(items::id.eq_any(&descendant_ids))
+                .filter(diesel::dsl::not(items::id.eq_any(&requested_ids)))
+                .order((items::position.asc(), items::id.asc()))
+                .select(items::id)
+                .load::<i64>(&self.connection)?,
+        );
+
+        // sub-items in the trash stay behind, without their parent
+        diesel::update(
+            items::table
+                .filter(items::parent_item_id.eq_any(&moved_ids))
+                .filter(diesel::dsl::not(items::id.eq_any(&moved_ids))),
+        )
+        .set(items::parent_item_id.eq(None::<i64>))
+        .execute(&self.connection)?;
+
+        let top_level_ids: Vec<i64> = moved
+            .iter()
+            .filter(|item| match item.parent_item_id {
+                Some(parent_id) => !moved_ids.contains(&parent_id),
+                None => false,
+            })
+            .map(|item| item.id)
+            .collect();
+        diesel::update(items::table.filter(items::id.eq_any(&top_level_ids)))
+            .set(items::parent_item_id.eq(None::<i64>))
+            .execute(&self.connection)?;
+
+        diesel::update(items::table.filter(items::id.eq_any(&moved_ids)))
+            .set(items::list_id.eq(target_list_id))
+            .execute(&self.connection)?;
+        Ok(moved_ids)
+    }
+
+    /// make sure the caller may work on the lists: their own ones, any list for admins.
+    /// Lists of other users are not found, like missing ones.
+    pub fn check_list_access(&self, list_ids: &[i64], caller: &Claims) -> Result<(), ApiError> {
+        use super::schema::lists::dsl::*;
+
+        let mut query = lists.filter(id.eq_any(list_ids)).select(id).into_boxed();
+        if !caller.has_scope(Scope::Admin) {
+            query = query.filter(user_id.eq(caller.sub));
+        }
+        let accessible = query
+            .load::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading lists"))?;
+        if list_ids.iter().any(|list_id| !accessible.contains(list_id)) {
+            return Err(ApiError::new("List not found", ErrorType::NotFound));
+        }
+        Ok(())
+    }
+
+    /// run a change of a list or its items in one transaction with recording it as a revision.
+    /// A list changed for the first time gets a baseline revision of its state before the change.
+    /// Only the owner of the list, or an admin, can change it.
+    pub fn revised<T, F>(
+        &self,
+        of_list_id: i64,
+        editor: &Claims,
+        action: &str,
+        mutation: F,
+    ) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Result<T, ApiError>,
+    {
+        self.revised_many(&[of_list_id], editor, action, mutation)
+    }
+
+    /// like `revised`, for changes spanning several lists, each of them gets a revision
+    pub fn revised_many<T, F>(
+        &self,
+        of_list_ids: &[i64],
+        editor: &Claims,
+        action: &str,
+        mutation: F,
+    ) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Result<T, ApiError>,
+    {
+        // always lock the lists in the same order so concurrent changes can not deadlock
+        let mut list_ids = of_list_ids.to_vec();
+        list_ids.sort();
+        list_ids.dedup();
+
+        self.check_list_access(&list_ids, editor)?;
+
+        self.connection.transaction::<_, ApiError, _>(|| {
+            for list_id in &list_ids {
+                self.record_revision(*list_id, None, "baseline", true)?;
+            }
+            let result = mutation()?;
+            for list_id in &list_ids {
+                self.check_budget(*list_id)?;
+                self.record_revision(*list_id, Some(editor.sub), action, false)?;
+            }
+            Ok(result)
+        })
+    }
+
+    /// compare what the checked items of a list cost with its budget and queue a notification
+    /// for the owner when spending reached a higher threshold than they were told about.
+    /// Dropping below a threshold resets it, so reaching it again notifies again.
+    fn check_budget(&self, of_list_id: i64) -> Result<(), ApiError> {
+        use super::schema::{items, lists};
+
+        let list = lists::table
+            .find(of_list_id)
+            .first::<List>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+        if list.budget_amount.is_none() && list.budget_notified_percent.is_none() {
+            return Ok(());
+        }
+
+        let list_items = Item::belonging_to(&list)
+            .filter(items::deleted_at.is_null())
+            .load::<Item>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading items"))?;
+        let status = BudgetStatus::of_list(&list, &Totals::of_items(&list_items));
+        let reached = status.as_ref().and_then(BudgetStatus::reached_threshold);
+        if reached == list.budget_notified_percent {
+            return Ok(());
+        }
+
+        diesel::update(lists::table.find(of_list_id))
+            .set(lists::budget_notified_percent.eq(reached))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while updating budget state"))?;
+
+        if let (Some(status), Some(threshold)) = (status, reached) {
+            let higher = list
+                .budget_notified_percent
+                .is_none_or(|notified| threshold > notified);
+            if higher && !list.is_template && list.deleted_at.is_none() {
+                self.queue_notification(&Notification::budget_threshold(
+                    &list, &status, threshold,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// set or remove the budget of a list
+    pub fn set_list_budget(
+        &self,
+        list_id: i64,
+        budget: Option<(BigDecimal, String)>,
+    ) -> Result<List, ApiError> {
+        use super::schema::lists::dsl::*;
+
+        let (new_amount, new_currency) = match budget {
+            Some((amount, currency)) => (Some(amount), Some(currency)),
+            None => (None, None),
+        };
+
+        diesel::update(lists.find(list_id).filter(deleted_at.is_null()))
+            .set((
+                budget_amount.eq(new_amount),
+                budget_currency.eq(new_currency),
+            ))
+            .get_result(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while setting budget"))
+    }
+
+    /// queue a notification, it is delivered by the notification dispatcher
+    pub fn queue_notification(&self, notification: &Notification) -> Result<(), ApiError> {
+        use super::schema::notifications;
+
+        diesel::insert_into(notifications::table)
+            .values(&CreateNotification {
+                user_id: notification.user_id(),
+                payload: notification.to_json()?,
+            })
+            .execute(&self.connection)
+            .map(|_| ())
+            .map_err(|err| ApiError::from_diesel_err(err, "while queueing notification"))
+    }
+
+    /// claim queued notifications for delivery, oldest first. Claims older than `stale_before`
+    /// are taken over, their dispatcher is assumed to have died before delivering them.
+    pub fn claim_notifications(
+        &self,
+        now: DateTime<Utc>,
+        stale_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingNotification>, ApiError> {
+        use super::schema::notifications::dsl::*;
+
+        self.connection
+            .transaction::<_, diesel::result::Error, _>(|| {
+                let pending = notifications
+                    .filter(claimed_at.is_null().or(claimed_at.lt(stale_before)))
+                    .order((created_at.asc(), id.asc()))
+                    .limit(limit)
+                    .for_update()
+                    .skip_locked()
+                    .load::<PendingNotification>(&self.connection)?;
+
+                let claimed_ids: Vec<i64> = pending.iter().map(|pending| pending.id).collect();
+                diesel::update(notifications.filter(id.eq_any(&claimed_ids)))
+                    .set(claimed_at.eq(now))
+                    .execute(&self.connection)?;
+                Ok(pending)
+            })
+            .map_err(|err| ApiError::from_diesel_err(err, "while claiming notifications"))
+    }
+
+    /// drop a delivered notification from the queue
+    pub fn delete_notification(&self, notification_id: i64) -> Result<usize, ApiError> {
+        use super::schema::notifications::dsl::*;
+
+        diesel::delete(notifications.find(notification_id))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while deleting notification"))
+    }
+
+    /// hand a claimed notification back to the queue so it is delivered again
+    pub fn release_notification(&self, notification_id: i64) -> Result<usize, ApiError> {
+        use super::schema::notifications::dsl::*;
+
+        diesel::update(notifications.find(notification_id))
+            .set(claimed_at.eq(None::<DateTime<Utc>>))
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while releasing notification"))
+    }
+
+    /// the current state of a list with its items, trashed items are left out
+    fn snapshot_list(&self, of_list_id: i64) -> Result<ListSnapshot, diesel::result::Error> {
+        use super::schema::items;
+        use super::schema::lists;
+
+        let list = lists::table
+            .find(of_list_id)
+            .first::<List>(&self.connection)?;
+        let list_items = Item::belonging_to(&list)
+            .filter(items::deleted_at.is_null())
+            .order((items::position.asc(), items::id.asc()))
+            .load::<Item>(&self.connection)?;
+
+        Ok(ListSnapshot {
+            list,
+            items: list_items,
+        })
+    }
+
+    /// store the current state of the list as its next revision together with the diff to the previous one.
+    /// Nothing is stored if the state did not change, or, for a baseline, if the list already has revisions.
+    fn record_revision(
+        &self,
+        of_list_id: i64,
+        author_id: Option<i64>,
+        revision_action: &str,
+        baseline: bool,
+    ) -> Result<(), ApiError> {
+        use super::schema::list_revisions::dsl::*;
+        use super::schema::lists;
+
+        // revisions of a list are numbered one after another, concurrent changes wait here
+        lists::table
+            .find(of_list_id)
+            .select(lists::id)
+            .for_update()
+            .first::<i64>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+
+        let last = list_revisions
+            .filter(list_id.eq(of_list_id))
+            .order(revision.desc())
+            .select((revision, snapshot))
+            .first::<(i64, serde_json::Value)>(&self.connection)
+            .optional()
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading last revision"))?;
+
+        let (last_revision, previous) = match last {
+            Some(_) if baseline => return Ok(()),
+            Some((number, value)) => (number, Some(ListSnapshot::from_json(value)?)),
+            None => (0, None),
+        };
+
+        let current = self
+            .snapshot_list(of_list_id)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading list"))?;
+        let changes = current.diff(previous.as_ref())?;
+        if changes.is_empty() && previous.is_some() {
+            return Ok(());
+        }
+
+        diesel::insert_into(list_revisions)
+            .values(&CreateRevision {
+                list_id: of_list_id,
+                revision: last_revision + 1,
+                user_id: author_id,
+                action: revision_action.to_string(),
+                diff: serde_json::Value::Object(changes),
+                snapshot: current.to_json()?,
+            })
+            .execute(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while recording revision"))?;
+        Ok(())
+    }
+
+    /// the revisions of a list, latest first, starting before the given revision number
+    pub fn get_revisions(
+        &self,
+        of_list_id: i64,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Revision>, ApiError> {
+        use super::schema::list_revisions::dsl::*;
+
+        self.get_live_list_id(of_list_id)?;
+
+        let mut query = list_revisions
+            .filter(list_id.eq(of_list_id))
+            .select((revision, user_id, action, diff, created_at))
+            .order(revision.desc())
+            .limit(limit)
+            .into_boxed();
+        if let Some(before_revision) = before {
+            query = query.filter(revision.lt(before_revision));
+        }
+
+        query
+            .load::<Revision>(&self.connection)
+            .map_err(|err| ApiError::from_diesel_err(err, "while loading revisions"))
+    }
+
+    /// put a list and its items back into the state recorded with the revision.
+    /// Items added since are moved to the trash, removed ones come back, even if they were purged.
+    pub fn revert_list(
+        &self,
+        of_list_id: i64,
+        to_revision: i64,
+        editor: &Claims,
+    ) -> Result<(), ApiError> {
+        use super::schema::list_revisions;
+        use super::schema::lists;
+
+        self.get_live_list_id(of_list_id)?;
+
+        self.revised(of_list_id, editor, "revert", || {
+            let recorded = list_revisions::table
+                .filter(list_revisions::list_id.eq(of_list_id))
+                .filter(list_revisions::revision.eq(to_revision))
+                .select(list_revisions::snapshot)
+                .first::<serde_json::Value>(&self.connection)
+                .map_err(|err| ApiError::from_diesel_err(err, "while loading revision"))?;
+            let target = ListSnapshot::from_json(recorded)?;
+
+            self.revert_items(of_list_id, &target.items)
+                .and_then(|_| {
+                    diesel::update(lists::table.find(of_list_id))
+                        .set((
+                            lists::title.eq(target.list.title),
+                            lists::subtitle.eq(target.list.subtitle),
+                            lists::budget_amount.eq(target.list.budget_amount),
+                            lists::budget_currency.eq(target.list.budget_currency),
+                        ))
+                        .execute(&self.connection)
+                })
+                .map_err(|err| ApiError::from_diesel_err(err, "while reverting list"))?;
+            Ok(())
+        })
+    }
+
+    /// make the live items of a list exactly the given ones. Items that were moved to another list since
+    /// stay there, the list gets copies of them with new ids.
+    fn revert_items(&self, of_list_id: i64, target: &[Item]) -> Result<(), diesel::result::Error> {
+        use super::schema::items::dsl::*;
+
+        let target_ids: Vec<i64> = target.iter().map(|item| item.id).collect();
+        let own_ids = items
+            .filter(id.eq_any(&target_ids))
+            .filter(list_id.eq(of_list_id))
+            .select(id)
+            .load::<i64>(&self.connection)?;
+        let moved_ids = items
+            .filter(id.eq_any(&target_ids))
+            .filter(list_id.ne(of_list_id))
+            .select(id)
+            .load::<i64>(&self.connection)?;
+
+        diesel::update(
+            items
+                .filter(list_id.eq(of_list_id))
+                .filter(deleted_at.is_null())
+                .filter(diesel::dsl::not(id.eq_any(&target_ids))),
+        )
+        .set(deleted_at.eq(Utc::now()))
+        .execute(&self.connection)?;
+
+        // the id each recorded item has after the revert
+        let mut reverted_ids: HashMap<i64, i64> = HashMap::new();
+        for item in target {
+            let values = (
+                list_id.eq(of_list_id),
+                title.eq(&item.title),
+                checked.eq(item.checked),
+                checked_at.eq(item.checked_at),
+                checked_by.eq(item.checked_by),
+                position.eq(item.position),
+                quantity.eq(&item.quantity),
+                unit.eq(item.unit),
+                deleted_at.eq(None::<DateTime<Utc>>),
+                due_at.eq(item.due_at),
+                remind_at.eq(item.remind_at),
+                notes.eq(&item.notes),
+                url.eq(&item.url),
+                unit_price.eq(&item.unit_price),
+                currency.eq(&item.currency),
+            );
+            let reverted_id = if own_ids.contains(&item.id) {
+                diesel::update(items.find(item.id))
+                    .set(values)
+                    .execute(&self.connection)?;
+                item.id
+            } else if moved_ids.contains(&item.id) {
+                diesel::insert_into(items)
+                    .values((created_at.eq(item.created_at), values))
+                    .returning(id)
+                    .get_result::<i64>(&self.connection)?
+            } else {
+                diesel::insert_into(items)
+                    .values((id.eq(item.id), created_at.eq(item.created_at), values))
+                    .execute(&self.connection)?;
+                item.id
+            };
+            reverted_ids.insert(item.id, reverted_id);
+        }
+
+        // nest the items once they all exist, parents may have been copied under a new id
+        for item in target {
+            let reverted_parent_id = item
+                .parent_item_id
+                .and_then(|of_parent_id| reverted_ids.get(&of_parent_id).cloned());
+            diesel::update(items.find(reverted_ids[&item.id]))
+                .set(parent_item_id.eq(reverted_parent_id))
+                .execute(&self.connection)?;
+        }
+        Ok(())
+    }
 }
@@ -9,6 +9,10 @@ pub enum ErrorType {
     NotFound,
     Internal,
     BadRequest,
+    Conflict,
+    UnprocessableEntity,
+    Unauthorized,
+    Forbidden,
     Webauthn,
 }
 
@@ -37,18 +41,30 @@ impl ApiError {
             ErrorType::NotFound => warp::http::StatusCode::NOT_FOUND,
             ErrorType::Internal => warp::http::StatusCode::INTERNAL_SERVER_ERROR,
             ErrorType::BadRequest => warp::http::StatusCode::BAD_REQUEST,
+            ErrorType::Conflict => warp::http::StatusCode::CONFLICT,
+            ErrorType::UnprocessableEntity => warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorType::Unauthorized => warp::http::StatusCode::UNAUTHORIZED,
+            ErrorType::Forbidden => warp::http::StatusCode::FORBIDDEN,
             ErrorType::Webauthn => warp::http::StatusCode::UNAUTHORIZED,
         }
     }
 
     pub fn from_diesel_err(err: diesel::result::Error, context: &str) -> ApiError {
+        if let diesel::result::Error::DatabaseError(kind, info) = &err {
+            match kind {
+                diesel::result::DatabaseErrorKind::UniqueViolation => {
+                    return ApiError::from_unique_violation(info.constraint_name(), context)
+                }
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation => {
+                    return ApiError::from_foreign_key_violation(info.constraint_name(), context)
+                }
+                _ => (),
+            }
+        }
+
         ApiError::new(
             format!("{}: {}", context, err.to_string()).as_str(),
             match err {
-                diesel::result::Error::DatabaseError(db_err, _) => match db_err {
-                    diesel::result::DatabaseErrorKind::UniqueViolation => ErrorType::BadRequest,
-                    _ => ErrorType::Internal,
-                },
                 diesel::result::Error::NotFound => ErrorType::NotFound,
                 // Here we can handle other cases if needed
                 _ => ErrorType::Internal,
@@ -56,6 +72,33 @@ impl ApiError {
         )
     }
 
+    /// name the field that is already taken instead of passing on the raw database message
+    fn from_unique_violation(constraint: Option<&str>, context: &str) -> ApiError {
+        let message = match constraint {
+            Some("users_email_unique_idx") => "email: a user with this email already exists",
+            Some("users_nick_unique_idx") => "nick: a user with this nick already exists",
+            Some("tags_user_id_name_unique_idx") => "name: a tag with this name already exists",
+            _ => "a record with these values already exists",
+        };
+        ApiError::new(
+            format!("{}: {}", context, message).as_str(),
+            ErrorType::Conflict,
+        )
+    }
+
+    /// name the reference that does not exist instead of failing with a 500
+    fn from_foreign_key_violation(constraint: Option<&str>, context: &str) -> ApiError {
+        let message = match constraint {
+            Some("lists_user_id_fkey") => "user_id: the referenced user does not exist",
+            Some("items_list_id_fkey") => "list_id: the referenced list does not exist",
+            _ => "a referenced record does not exist",
+        };
+        ApiError::new(
+            format!("{}: {}", context, message).as_str(),
+            ErrorType::UnprocessableEntity,
+        )
+    }
+
     pub fn from_webauthn_error(err: webauthn_rs::error::WebauthnError, context: &str) -> ApiError {
         ApiError::new(
             format!("{}: {}", context, err.to_string()).as_str(),
@@ -69,6 +112,13 @@ impl ApiError {
 
 impl std::error::Error for ApiError {}
 
+// needed by transactions returning ApiError, begin/commit failures end up here
+impl From<diesel::result::Error> for ApiError {
+    fn from(err: diesel::result::Error) -> ApiError {
+        ApiError::from_diesel_err(err, "while running transaction")
+    }
+}
+
 impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.message)
@@ -90,6 +140,9 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
         code = warp::http::StatusCode::BAD_REQUEST;
         message = "Invalid Body";
+    } else if let Some(_) = err.find::<warp::reject::InvalidQuery>() {
+        code = warp::http::StatusCode::BAD_REQUEST;
+        message = "Invalid Query";
     } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
         code = warp::http::StatusCode::METHOD_NOT_ALLOWED;
         message = "Method Not Allowed";
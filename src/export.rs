@@ -0,0 +1,139 @@
+use serde::Serialize;
+use warp::hyper::body::{Body, Bytes, Sender};
+
+use crate::db;
+use crate::errors::{ApiError, ErrorType};
+use crate::models::User;
+use crate::webauthn::actors::CredentialMetadata;
+
+/// number of lists (plus their items), revisions or notifications loaded per query while exporting
+const EXPORT_BATCH_SIZE: i64 = 100;
+
+/// One line of the export: a single entity tagged with its type
+#[derive(Serialize)]
+struct ExportRecord<T: Serialize> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    data: T,
+}
+
+fn to_line<T: Serialize>(kind: &'static str, data: T) -> Result<Bytes, ApiError> {
+    let mut line = serde_json::to_vec(&ExportRecord { kind, data }).map_err(|err| {
+        ApiError::new(
+            format!("while serializing export: {}", err.to_string()).as_str(),
+            ErrorType::Internal,
+        )
+    })?;
+    line.push(b'\n');
+    Ok(Bytes::from(line))
+}
+
+async fn send(sender: &mut Sender, line: Bytes) -> Result<(), ApiError> {
+    sender.send_data(line).await.map_err(|err| {
+        ApiError::new(
+            format!("while sending export: {}", err.to_string()).as_str(),
+            ErrorType::Internal,
+        )
+    })
+}
+
+/// Write everything stored about the user as newline delimited JSON, one entity per line.
+/// Lists, items, revisions and notifications are loaded in batches so the export never holds
+/// the whole account in memory.
+async fn write_export(
+    sender: &mut Sender,
+    user: User,
+    credentials: Vec<CredentialMetadata>,
+    db_manager: db::DBManager,
+) -> Result<(), ApiError> {
+    send(sender, to_line("user", &user)?).await?;
+
+    for credential in credentials {
+        send(sender, to_line("credential", credential)?).await?;
+    }
+
+    let mut last_list_id = 0;
+    loop {
+        let lists = db_manager.get_user_lists_page(user.id, last_list_id, EXPORT_BATCH_SIZE)?;
+        let items = db_manager.get_items_of_lists(&lists)?;
+
+        let mut lines = Vec::with_capacity(lists.len() + items.len());
+        for list in &lists {
+            lines.push(to_line("list", list)?);
+        }
+        for item in &items {
+            lines.push(to_line("item", item)?);
+        }
+        for line in lines {
+            send(sender, line).await?;
+        }
+
+        // the history of the lists, snapshots included
+        let list_ids: Vec<i64> = lists.iter().map(|list| list.id).collect();
+        let mut last_revision_id = 0;
+        loop {
+            let revisions =
+                db_manager.get_revisions_page(&list_ids, last_revision_id, EXPORT_BATCH_SIZE)?;
+            for revision in &revisions {
+                send(sender, to_line("revision", revision)?).await?;
+            }
+
+            match revisions.last() {
+                Some(revision) if revisions.len() as i64 == EXPORT_BATCH_SIZE => {
+                    last_revision_id = revision.id
+                }
+                _ => break,
+            }
+        }
+
+        match lists.last() {
+            Some(list) if lists.len() as i64 == EXPORT_BATCH_SIZE => last_list_id = list.id,
+            _ => break,
+        }
+    }
+
+    // notifications not delivered yet
+    let mut last_notification_id = 0;
+    loop {
+        let notifications = db_manager.get_user_notifications_page(
+            user.id,
+            last_notification_id,
+            EXPORT_BATCH_SIZE,
+        )?;
+        for notification in &notifications {
+            send(sender, to_line("notification", notification)?).await?;
+        }
+
+        match notifications.last() {
+            Some(notification) if notifications.len() as i64 == EXPORT_BATCH_SIZE => {
+                last_notification_id = notification.id
+            }
+            _ => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Start streaming the export of the user into a response body
+pub fn stream_export(
+    user: User,
+    credentials: Vec<CredentialMetadata>,
+    db_manager: db::DBManager,
+) -> Body {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        let user_id = user.id;
+        match write_export(&mut sender, user, credentials, db_manager).await {
+            Ok(_) => log::info!("completed export for user {}", user_id),
+            Err(err) => {
+                log::error!("export for user {} failed: {}", user_id, err);
+                // abort so the client sees a failed download instead of a truncated one
+                sender.abort();
+            }
+        }
+    });
+
+    body
+}
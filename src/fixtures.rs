@@ -0,0 +1,36 @@
+use serde_json::json;
+
+use crate::models::{Item, List};
+
+/// an active list of user 7, created and last updated at 2021-05-10T10:00:00Z
+pub fn list(id: i64, title: &str) -> List {
+    serde_json::from_value(json!({
+        "id": id,
+        "user_id": 7,
+        "title": title,
+        "subtitle": "",
+        "created_at": "2021-05-10T10:00:00Z",
+        "updated_at": "2021-05-10T10:00:00Z",
+        "deleted_at": null,
+    }))
+    .unwrap()
+}
+
+/// an unchecked item of list 1 with a quantity of 1 pcs, positioned by its id
+pub fn item(id: i64, title: &str) -> Item {
+    serde_json::from_value(json!({
+        "id": id,
+        "list_id": 1,
+        "title": title,
+        "created_at": "2021-05-10T10:00:00Z",
+        "updated_at": "2021-05-10T10:00:00Z",
+        "checked": false,
+        "checked_at": null,
+        "checked_by": null,
+        "position": id * 1024,
+        "quantity": "1",
+        "unit": "pcs",
+        "deleted_at": null,
+    }))
+    .unwrap()
+}
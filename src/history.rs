@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::{ApiError, ErrorType};
+use crate::models::{Item, List};
+
+/// fields that change with every write or are bookkeeping, they say nothing about the change itself
+const IGNORED_FIELDS: [&str; 3] = ["created_at", "updated_at", "reminded_at"];
+
+/// State of a list and its items, stored with every revision
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListSnapshot {
+    pub list: List,
+    pub items: Vec<Item>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Value, ApiError> {
+    serde_json::to_value(value).map_err(|err| {
+        ApiError::new(
+            format!("while serializing revision: {}", err.to_string()).as_str(),
+            ErrorType::Internal,
+        )
+    })
+}
+
+/// the fields of `after` that differ from `before`, each as {"from": .., "to": ..}
+fn changed_fields(before: Option<&Value>, after: &Value) -> Map<String, Value> {
+    let mut changes = Map::new();
+
+    if let Some(fields) = after.as_object() {
+        for (field, value) in fields {
+            if IGNORED_FIELDS.contains(&field.as_str()) {
+                continue;
+            }
+            let previous = before
+                .and_then(|before| before.get(field))
+                .unwrap_or(&Value::Null);
+            if previous != value {
+                let mut change = Map::new();
+                change.insert(String::from("from"), previous.clone());
+                change.insert(String::from("to"), value.clone());
+                changes.insert(field.clone(), Value::Object(change));
+            }
+        }
+    }
+    changes
+}
+
+impl ListSnapshot {
+    pub fn to_json(&self) -> Result<Value, ApiError> {
+        to_json(self)
+    }
+
+    pub fn from_json(value: Value) -> Result<ListSnapshot, ApiError> {
+        serde_json::from_value(value).map_err(|err| {
+            ApiError::new(
+                format!("while reading revision: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })
+    }
+
+    /// what changed since the previous snapshot: changed list fields and added, removed and changed items.
+    /// Empty if nothing changed.
+    pub fn diff(&self, previous: Option<&ListSnapshot>) -> Result<Map<String, Value>, ApiError> {
+        let mut diff = Map::new();
+
+        let previous_list = previous
+            .map(|snapshot| to_json(&snapshot.list))
+            .transpose()?;
+        let list_changes = changed_fields(previous_list.as_ref(), &to_json(&self.list)?);
+        if !list_changes.is_empty() {
+            diff.insert(String::from("list"), Value::Object(list_changes));
+        }
+
+        let previous_items: HashMap<i64, &Item> = previous
+            .map(|snapshot| snapshot.items.iter().map(|item| (item.id, item)).collect())
+            .unwrap_or_default();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for item in &self.items {
+            match previous_items.get(&item.id) {
+                None => added.push(to_json(item)?),
+                Some(previous_item) => {
+                    let mut changes =
+                        changed_fields(Some(&to_json(previous_item)?), &to_json(item)?);
+                    if !changes.is_empty() {
+                        changes.insert(String::from("id"), Value::from(item.id));
+                        changed.push(Value::Object(changes));
+                    }
+                }
+            }
+        }
+
+        let current_ids: HashSet<i64> = self.items.iter().map(|item| item.id).collect();
+        let mut removed = Vec::new();
+        for item in previous.map_or(&[][..], |snapshot| &snapshot.items[..]) {
+            if !current_ids.contains(&item.id) {
+                removed.push(to_json(item)?);
+            }
+        }
+
+        for (key, entries) in [("added", added), ("removed", removed), ("changed", changed)] {
+            if !entries.is_empty() {
+                diff.insert(String::from(key), Value::Array(entries));
+            }
+        }
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn item(id: i64, title: &str, checked: bool) -> Item {
+        let mut item = fixtures::item(id, title);
+        item.checked = checked;
+        item
+    }
+
+    fn snapshot(title: &str, items: Vec<Item>) -> ListSnapshot {
+        ListSnapshot {
+            list: fixtures::list(1, title),
+            items,
+        }
+    }
+
+    #[test]
+    fn first_snapshot_adds_everything() {
+        let current = snapshot("Groceries", vec![item(1, "Milk", false)]);
+
+        let diff = current.diff(None).unwrap();
+
+        assert_eq!(
+            diff["list"]["title"],
+            json!({"from": null, "to": "Groceries"})
+        );
+        assert_eq!(diff["added"][0]["title"], json!("Milk"));
+        assert!(diff.get("removed").is_none());
+        assert!(diff.get("changed").is_none());
+    }
+
+    #[test]
+    fn unchanged_snapshot_has_empty_diff() {
+        let previous = snapshot("Groceries", vec![item(1, "Milk", false)]);
+        let mut current = previous.clone();
+        current.items[0].updated_at = "2021-05-11T10:00:00Z".parse().unwrap();
+
+        assert!(current.diff(Some(&previous)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_lists_changed_fields_of_items_with_their_id() {
+        let previous = snapshot("Groceries", vec![item(1, "Milk", false)]);
+        let current = snapshot("Groceries", vec![item(1, "Oat milk", true)]);
+
+        let diff = current.diff(Some(&previous)).unwrap();
+
+        assert!(diff.get("list").is_none());
+        assert_eq!(
+            diff["changed"],
+            json!([{
+                "id": 1,
+                "title": {"from": "Milk", "to": "Oat milk"},
+                "checked": {"from": false, "to": true},
+            }])
+        );
+    }
+
+    #[test]
+    fn diff_lists_added_and_removed_items() {
+        let previous = snapshot("Groceries", vec![item(1, "Milk", false)]);
+        let current = snapshot("Weekend", vec![item(2, "Bread", false)]);
+
+        let diff = current.diff(Some(&previous)).unwrap();
+
+        assert_eq!(
+            diff["list"]["title"],
+            json!({"from": "Groceries", "to": "Weekend"})
+        );
+        assert_eq!(diff["added"][0]["id"], json!(2));
+        assert_eq!(diff["removed"][0]["id"], json!(1));
+    }
+}
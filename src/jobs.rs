@@ -0,0 +1,274 @@
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db;
+use crate::errors::{ApiError, ErrorType};
+use crate::notify::{Notification, Notifier};
+use crate::webauthn::actors::WebauthnActor;
+use crate::PgPool;
+
+/// how often we look for accounts whose grace period expired
+const ACCOUNT_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// how often we look for recurring templates that are due
+const RECURRENCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// how often we look for item reminders that are due
+const REMINDER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// reminders claimed at once, the rest follow in the next batch
+const REMINDER_BATCH_SIZE: i64 = 50;
+
+/// how often we look for queued notifications, like budget warnings
+const NOTIFICATION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// notifications claimed at once, the rest follow in the next batch
+const NOTIFICATION_BATCH_SIZE: i64 = 50;
+
+/// how long a claimed notification may take before another dispatcher takes it over
+const NOTIFICATION_CLAIM_TIMEOUT_MINUTES: i64 = 10;
+
+/// how often we look for lists and items that stayed in the trash past the retention period
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn db_manager(pool: &PgPool) -> Result<db::DBManager, ApiError> {
+    pool.get().map(db::DBManager::new).map_err(|err| {
+        ApiError::new(
+            format!("Error getting connection from pool: {}", err.to_string()).as_str(),
+            ErrorType::Internal,
+        )
+    })
+}
+
+/// Run database work on the blocking thread pool instead of the tasks of the runtime.
+/// The connection goes back to the pool when the work is done, before anything else is awaited.
+async fn run_db<T, F>(pool: &PgPool, work: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&db::DBManager) -> Result<T, ApiError> + Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || db_manager(&pool).and_then(|db_manager| work(&db_manager)))
+        .await
+        .map_err(|err| {
+            ApiError::new(
+                format!("while running database work: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })?
+}
+
+/// Background task: permanently delete accounts marked for deletion longer than the grace period ago
+pub async fn purge_deleted_accounts(
+    pool: PgPool,
+    actor: Arc<WebauthnActor>,
+    grace_period: chrono::Duration,
+) {
+    let mut interval = tokio::time::interval(ACCOUNT_PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let purged = run_db(&pool, move |db_manager| {
+            db_manager.purge_users(Utc::now() - grace_period)
+        })
+        .await;
+
+        match purged {
+            Ok(users) => {
+                for user in users {
+                    actor.remove_credentials(&user.nick).await;
+                    log::info!("purged user {} after deletion grace period", user.id);
+                }
+            }
+            Err(err) => log::error!("Error while purging deleted accounts: {}", err),
+        }
+    }
+}
+
+/// Background task: permanently delete lists and items that were moved to the trash longer than the retention period ago
+pub async fn purge_trash(pool: PgPool, retention_period: chrono::Duration) {
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let purged = run_db(&pool, move |db_manager| {
+            db_manager.purge_trash(Utc::now() - retention_period)
+        })
+        .await;
+
+        match purged {
+            Ok((0, 0)) => (),
+            Ok((lists, items)) => {
+                log::info!("purged {} lists and {} items from the trash", lists, items)
+            }
+            Err(err) => log::error!("Error while purging trash: {}", err),
+        }
+    }
+}
+
+/// Background task: create the lists of recurring templates when they are due.
+/// Safe to run on several instances at once, each occurrence is claimed by exactly one of them.
+pub async fn materialize_recurring_lists(pool: PgPool) {
+    let mut interval = tokio::time::interval(RECURRENCE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // everything happens in the database, the whole run stays on the blocking pool
+        let result = run_db(&pool, |db_manager| loop {
+            match db_manager.run_due_recurrence(Utc::now()) {
+                Ok(Some((recurrence, Some(list)))) => log::info!(
+                    "created list {} from template {}",
+                    list.id,
+                    recurrence.template_id
+                ),
+                Ok(Some((recurrence, None))) => match &recurrence.last_error {
+                    Some(err) => log::error!(
+                        "Error while creating list from template {}, skipped occurrence: {}",
+                        recurrence.template_id,
+                        err
+                    ),
+                    None => log::info!(
+                        "skipped occurrence of template {} in the trash",
+                        recurrence.template_id
+                    ),
+                },
+                Ok(None) => return Ok(()),
+                // failures of single templates are recorded on them, this is the database itself
+                Err(err) => return Err(err),
+            }
+        })
+        .await;
+
+        if let Err(err) = result {
+            log::error!("Error while running recurrences: {}", err);
+        }
+    }
+}
+
+/// Background task: send the reminders of items whose remind_at has come through the notifier.
+/// Reminders that could not be delivered are sent again on the next run.
+pub async fn dispatch_reminders(pool: PgPool, notifier: Arc<dyn Notifier>) {
+    let mut interval = tokio::time::interval(REMINDER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        loop {
+            let due = match run_db(&pool, |db_manager| {
+                db_manager.claim_due_reminders(Utc::now(), REMINDER_BATCH_SIZE)
+            })
+            .await
+            {
+                Ok(due) => due,
+                Err(err) => {
+                    log::error!("Error while dispatching reminders: {}", err);
+                    break;
+                }
+            };
+            let claimed = due.len() as i64;
+
+            let mut failed = false;
+            for (item, list) in due {
+                let notification = Notification::item_reminder(&list, &item);
+                if let Err(err) = notifier.notify(&notification).await {
+                    log::error!("Error while sending reminder for item {}: {}", item.id, err);
+                    failed = true;
+                    let item_id = item.id;
+                    let released = run_db(&pool, move |db_manager| {
+                        db_manager.release_reminder(item_id)
+                    })
+                    .await;
+                    if let Err(err) = released {
+                        log::error!(
+                            "Error while releasing reminder for item {}: {}",
+                            item.id,
+                            err
+                        );
+                    }
+                }
+            }
+
+            // a failing notifier is tried again on the next run instead of right away
+            if failed || claimed < REMINDER_BATCH_SIZE {
+                break;
+            }
+        }
+    }
+}
+
+/// Background task: deliver the notifications queued by changes, e.g. when a list went over budget.
+/// Notifications that could not be delivered stay queued and are tried again on the next run.
+pub async fn dispatch_notifications(pool: PgPool, notifier: Arc<dyn Notifier>) {
+    let mut interval = tokio::time::interval(NOTIFICATION_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        loop {
+            let now = Utc::now();
+            let stale_before = now - chrono::Duration::minutes(NOTIFICATION_CLAIM_TIMEOUT_MINUTES);
+            let pending = match run_db(&pool, move |db_manager| {
+                db_manager.claim_notifications(now, stale_before, NOTIFICATION_BATCH_SIZE)
+            })
+            .await
+            {
+                Ok(pending) => pending,
+                Err(err) => {
+                    log::error!("Error while dispatching notifications: {}", err);
+                    break;
+                }
+            };
+            let claimed = pending.len() as i64;
+
+            let mut failed = false;
+            for queued in pending {
+                let notification_id = queued.id;
+                // a payload that can not be read will not get better by trying again
+                let delivered = match Notification::from_json(queued.payload) {
+                    Ok(notification) => notifier.notify(&notification).await,
+                    Err(err) => {
+                        log::error!("Dropping notification {}: {}", notification_id, err);
+                        Ok(())
+                    }
+                };
+
+                let result = match delivered {
+                    Ok(()) => {
+                        run_db(&pool, move |db_manager| {
+                            db_manager.delete_notification(notification_id)
+                        })
+                        .await
+                    }
+                    Err(err) => {
+                        log::error!(
+                            "Error while sending notification {}: {}",
+                            notification_id,
+                            err
+                        );
+                        failed = true;
+                        run_db(&pool, move |db_manager| {
+                            db_manager.release_notification(notification_id)
+                        })
+                        .await
+                    }
+                };
+                if let Err(err) = result {
+                    log::error!(
+                        "Error while updating notification {}: {}",
+                        notification_id,
+                        err
+                    );
+                }
+            }
+
+            // a failing notifier is tried again on the next run instead of right away
+            if failed || claimed < NOTIFICATION_BATCH_SIZE {
+                break;
+            }
+        }
+    }
+}
@@ -1,10 +1,22 @@
+#![recursion_limit = "256"]
+
 #[macro_use]
 extern crate diesel;
 
 mod api;
+mod auth;
 mod db;
 mod errors;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod history;
+mod jobs;
+mod matching;
 mod models;
+mod notify;
+mod pagination;
+mod recurrence;
 mod routes;
 mod schema;
 mod webauthn;
@@ -25,9 +37,12 @@ use warp::Filter;
 pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 use webauthn_rs::ephemeral::WebauthnEphemeralConfig;
 
-fn pg_pool(db_url: &str) -> PgPool {
+fn pg_pool(db_url: &str, search_language: db::SearchLanguage) -> PgPool {
     let manager = ConnectionManager::<PgConnection>::new(db_url);
-    return Pool::new(manager).expect("Postgres connection pool could not be created");
+    return Pool::builder()
+        .connection_customizer(Box::new(search_language))
+        .build(manager)
+        .expect("Postgres connection pool could not be created");
 }
 
 pub fn with_db_access_manager(
@@ -92,7 +107,11 @@ async fn main() {
 
     // set up database
     let database_url = env::var("DATABASE_URL").expect("Add DATABASE_URL to yur .env file");
-    let pg_pool = pg_pool(database_url.as_str());
+    let search_language = env::var("SEARCH_LANGUAGE").unwrap_or_else(|_| String::from("english"));
+    info!("Full text search language {:?} ", search_language);
+    let search_language = db::SearchLanguage::new(search_language.as_str())
+        .expect("SEARCH_LANGUAGE in .env invalid! Use a Postgres text search configuration name.");
+    let pg_pool = pg_pool(database_url.as_str(), search_language);
 
     // set up Webauthn relying party parameters
     let webauthn_rp_name = env::var("WEBAUTHN_RELYING_PARTY_NAME")
@@ -117,27 +136,154 @@ async fn main() {
     let wan = crate::webauthn::actors::WebauthnActor::new(wan_c);
     let actor = Arc::new(wan);
 
+    // set up JWT signing for scoped access tokens
+    let jwt_secret = env::var("JWT_SECRET").expect("Add JWT_SECRET to yur .env file");
+    let jwt_lifetime_hours: i64 = env::var("JWT_TOKEN_LIFETIME_HOURS")
+        .unwrap_or_else(|_| String::from("24"))
+        .parse()
+        .expect("JWT_TOKEN_LIFETIME_HOURS in .env invalid! Use a number of hours.");
+    // admin access to all users' data is only granted to the user ids listed here
+    let admin_user_ids: Vec<i64> = env::var("ADMIN_USER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| {
+            id.trim()
+                .parse()
+                .expect("ADMIN_USER_IDS in .env invalid! Use a comma separated list of user ids.")
+        })
+        .collect();
+    let auth = Arc::new(auth::Auth::new(
+        jwt_secret,
+        chrono::Duration::hours(jwt_lifetime_hours),
+        admin_user_ids,
+    ));
+
+    // purge accounts marked for deletion once their grace period expired
+    let deletion_grace_days: i64 = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+        .unwrap_or_else(|_| String::from("30"))
+        .parse()
+        .expect("ACCOUNT_DELETION_GRACE_DAYS in .env invalid! Use a number of days.");
+    tokio::spawn(jobs::purge_deleted_accounts(
+        pg_pool.clone(),
+        actor.clone(),
+        chrono::Duration::days(deletion_grace_days),
+    ));
+
+    // permanently delete lists and items that stayed in the trash past the retention period
+    let trash_retention_days: i64 = env::var("TRASH_RETENTION_DAYS")
+        .unwrap_or_else(|_| String::from("30"))
+        .parse()
+        .expect("TRASH_RETENTION_DAYS in .env invalid! Use a number of days.");
+    tokio::spawn(jobs::purge_trash(
+        pg_pool.clone(),
+        chrono::Duration::days(trash_retention_days),
+    ));
+
+    // create lists from recurring templates when they are due
+    tokio::spawn(jobs::materialize_recurring_lists(pg_pool.clone()));
+
+    // deliver notifications to a webhook if one is configured, to the log otherwise
+    let notifier: Arc<dyn notify::Notifier> = match env::var("NOTIFICATION_WEBHOOK_URL") {
+        Ok(webhook_url) => {
+            info!("Sending notifications to {:?}", webhook_url);
+            Arc::new(notify::WebhookNotifier::new(webhook_url.as_str()).expect(
+                "NOTIFICATION_WEBHOOK_URL in .env invalid! Use an http:// or https:// URL.",
+            ))
+        }
+        Err(_) => Arc::new(notify::LogNotifier),
+    };
+
+    // send item reminders when they are due
+    tokio::spawn(jobs::dispatch_reminders(pg_pool.clone(), notifier.clone()));
+
+    // deliver notifications queued by changes, like budget warnings
+    tokio::spawn(jobs::dispatch_notifications(
+        pg_pool.clone(),
+        notifier.clone(),
+    ));
+
     // set up the routes
 
     // Webauthn: Add path prefix /auth to all these routes
     let auth_routes = warp::path!("auth" / ..).and(
         webauthn::routes::challenge_register(actor.clone())
-            .or(webauthn::routes::register(pg_pool.clone(), actor.clone()))
-            .or(webauthn::routes::challenge_login(actor)),
+            .or(webauthn::routes::register(
+                pg_pool.clone(),
+                actor.clone(),
+                auth.clone(),
+            ))
+            .or(webauthn::routes::challenge_login(actor.clone()))
+            .or(webauthn::routes::login(
+                pg_pool.clone(),
+                actor.clone(),
+                auth.clone(),
+            )),
     );
 
     // API: Add path prefix /api to all our routes
     let api_routes = warp::path!("api" / ..).and(
         // list routes
-        routes::add_list(pg_pool.clone())
-            .or(routes::get_lists(pg_pool.clone()))
-            .or(routes::get_list(pg_pool.clone()))
-            .or(routes::update_list(pg_pool.clone()))
-            .or(routes::delete_list(pg_pool.clone()))
+        routes::add_list(pg_pool.clone(), auth.clone())
+            .or(routes::get_lists(pg_pool.clone(), auth.clone()))
+            .or(routes::get_list(pg_pool.clone(), auth.clone()))
+            .or(routes::search(pg_pool.clone(), auth.clone()))
+            .or(routes::update_list(pg_pool.clone(), auth.clone()))
+            .or(routes::delete_list(pg_pool.clone(), auth.clone()))
+            .or(routes::reorder_items(pg_pool.clone(), auth.clone()))
+            .or(routes::clear_checked_items(pg_pool.clone(), auth.clone()))
+            .or(routes::get_history(pg_pool.clone(), auth.clone()))
+            .or(routes::revert_list(pg_pool.clone(), auth.clone()))
+            .or(routes::clone_list(pg_pool.clone(), auth.clone()))
+            .or(routes::set_list_budget(pg_pool.clone(), auth.clone()))
+            .or(routes::delete_list_budget(pg_pool.clone(), auth.clone()))
+            // tag routes
+            .or(routes::get_tags(pg_pool.clone(), auth.clone()))
+            .or(routes::add_tag(pg_pool.clone(), auth.clone()))
+            .or(routes::rename_tag(pg_pool.clone(), auth.clone()))
+            .or(routes::delete_tag(pg_pool.clone(), auth.clone()))
+            .or(routes::get_list_tags(pg_pool.clone(), auth.clone()))
+            .or(routes::tag_list(pg_pool.clone(), auth.clone()))
+            .or(routes::untag_list(pg_pool.clone(), auth.clone()))
+            // template routes
+            .or(routes::get_templates(pg_pool.clone(), auth.clone()))
+            .or(routes::instantiate_template(pg_pool.clone(), auth.clone()))
+            .or(routes::set_recurrence(pg_pool.clone(), auth.clone()))
+            .or(routes::get_recurrence(pg_pool.clone(), auth.clone()))
+            .or(routes::delete_recurrence(pg_pool.clone(), auth.clone()))
             // item routes
-            .or(routes::add_item(pg_pool.clone()))
-            .or(routes::update_item(pg_pool.clone()))
-            .or(routes::delete_item(pg_pool)),
+            .or(routes::add_item(pg_pool.clone(), auth.clone()))
+            .or(routes::batch_items(pg_pool.clone(), auth.clone()))
+            .or(routes::update_item(pg_pool.clone(), auth.clone()))
+            .or(routes::set_item_parent(pg_pool.clone(), auth.clone()))
+            .or(routes::set_item_details(pg_pool.clone(), auth.clone()))
+            .or(routes::set_item_schedule(pg_pool.clone(), auth.clone()))
+            .or(routes::get_due_items(pg_pool.clone(), auth.clone()))
+            .or(routes::toggle_item(pg_pool.clone(), auth.clone()))
+            .or(routes::delete_item(pg_pool.clone(), auth.clone()))
+            .or(routes::move_item(pg_pool.clone(), auth.clone()))
+            .or(routes::copy_item(pg_pool.clone(), auth.clone()))
+            .or(routes::move_items(pg_pool.clone(), auth.clone()))
+            .or(routes::copy_items(pg_pool.clone(), auth.clone()))
+            // trash routes
+            .or(routes::get_trash(pg_pool.clone(), auth.clone()))
+            .or(routes::restore(pg_pool.clone(), auth.clone()))
+            // profile routes
+            .or(routes::get_me(pg_pool.clone(), auth.clone()))
+            .or(routes::update_me(
+                pg_pool.clone(),
+                auth.clone(),
+                actor.clone(),
+            ))
+            .or(routes::verify_email(pg_pool.clone(), auth.clone()))
+            .or(routes::export_me(
+                pg_pool.clone(),
+                auth.clone(),
+                actor.clone(),
+            ))
+            .or(routes::delete_me(pg_pool.clone(), auth.clone()))
+            // token routes
+            .or(routes::create_token(auth)),
     );
 
     // assemble all routes, add error handler
@@ -0,0 +1,157 @@
+use serde::Deserialize;
+
+use crate::errors::{ApiError, ErrorType};
+
+/// similarity two titles need to count as the same product unless the request asks for another,
+/// only titles that are the same after normalizing, fuzzy matching is up to the client
+pub const DEFAULT_SIMILARITY_THRESHOLD: f64 = 1.0;
+
+/// What to do when an added item looks like one already on the list
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDuplicate {
+    // add the quantity to the existing item if the units and details are compatible
+    #[default]
+    Merge,
+    // refuse with a conflict naming the existing item
+    Reject,
+    // add it anyway
+    Allow,
+}
+
+/// How duplicates are detected and handled when adding an item
+#[derive(Debug, Clone, Copy)]
+pub struct DuplicatePolicy {
+    pub on_duplicate: OnDuplicate,
+    // 1.0 only matches titles that are the same after normalizing
+    pub threshold: f64,
+}
+
+impl DuplicatePolicy {
+    pub fn new(on_duplicate: OnDuplicate, threshold: Option<f64>) -> Result<Self, ApiError> {
+        let threshold = threshold.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD);
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            return Err(ApiError::new(
+                "similarity: must be greater than 0 and at most 1",
+                ErrorType::BadRequest,
+            ));
+        }
+        Ok(DuplicatePolicy {
+            on_duplicate,
+            threshold,
+        })
+    }
+}
+
+/// singular of a word for the simple english plurals, "berries", "boxes", "tomatoes", "eggs"
+fn singular(word: &str) -> String {
+    let len = word.len();
+    if len > 4 && word.ends_with("ies") {
+        format!("{}y", &word[..len - 3])
+    } else if len > 4
+        && ["ches", "shes", "sses", "xes", "zes", "oes"]
+            .iter()
+            .any(|suffix| word.ends_with(suffix))
+    {
+        word[..len - 2].to_string()
+    } else if len > 3
+        && word.ends_with('s')
+        && !["ss", "us", "is"]
+            .iter()
+            .any(|suffix| word.ends_with(suffix))
+    {
+        word[..len - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+/// lowercase, single spaces and singular words, so "  Fresh   Eggs" and "fresh egg" compare equal
+fn normalize_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split_whitespace()
+        .map(singular)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// 1 minus the edit distance relative to the longer title, 1.0 for equal titles
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    // levenshtein distance keeping one row of the matrix
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + if a_char == b_char { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    1.0 - row[b.len()] as f64 / longest as f64
+}
+
+/// how alike two item titles are from 0 to 1, compared after normalizing them
+pub fn title_similarity(title: &str, other: &str) -> f64 {
+    similarity(&normalize_title(title), &normalize_title(other))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn singular_handles_simple_plurals() {
+        assert_eq!(singular("eggs"), "egg");
+        assert_eq!(singular("berries"), "berry");
+        assert_eq!(singular("boxes"), "box");
+        assert_eq!(singular("tomatoes"), "tomato");
+        assert_eq!(singular("peaches"), "peach");
+    }
+
+    #[test]
+    fn singular_keeps_words_that_are_no_plurals() {
+        assert_eq!(singular("glass"), "glass");
+        assert_eq!(singular("hummus"), "hummus");
+        assert_eq!(singular("pies"), "pie");
+        assert_eq!(singular("gas"), "gas");
+        assert_eq!(singular("milk"), "milk");
+    }
+
+    #[test]
+    fn titles_are_compared_after_normalizing() {
+        assert_eq!(title_similarity("  Fresh   Eggs", "fresh egg"), 1.0);
+        assert_eq!(title_similarity("Berries", "berry"), 1.0);
+    }
+
+    #[test]
+    fn similarity_drops_with_edits() {
+        let typo = title_similarity("Bananas", "bananna");
+        assert!((0.8..1.0).contains(&typo), "{}", typo);
+        assert!(title_similarity("milk", "bread") < 0.5);
+        assert_eq!(title_similarity("", ""), 1.0);
+    }
+
+    #[test]
+    fn default_policy_only_matches_same_titles() {
+        let policy = DuplicatePolicy::new(OnDuplicate::Merge, None).unwrap();
+        assert!(title_similarity("banana", "bananna") < policy.threshold);
+        assert!(title_similarity("Tomatoes", "tomatos") >= policy.threshold);
+    }
+
+    #[test]
+    fn policy_rejects_thresholds_out_of_range() {
+        assert!(DuplicatePolicy::new(OnDuplicate::Merge, Some(0.0)).is_err());
+        assert!(DuplicatePolicy::new(OnDuplicate::Merge, Some(1.5)).is_err());
+        assert!(DuplicatePolicy::new(OnDuplicate::Merge, Some(0.8)).is_ok());
+    }
+}
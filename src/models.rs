@@ -1,7 +1,20 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{BigInt, Float4, Text};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
 
 use crate::schema::items;
+use crate::schema::list_revisions;
+use crate::schema::list_tags;
 use crate::schema::lists;
+use crate::schema::notifications;
+use crate::schema::recurrences;
+use crate::schema::tags;
 use crate::schema::users;
 
 /// Users
@@ -12,6 +25,10 @@ pub struct User {
     pub id: i64,
     pub nick: String,
     pub email: String,
+    pub pending_email: Option<String>,
+    pub deletion_requested_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Insertable)]
@@ -21,9 +38,79 @@ pub struct CreateUser {
     pub email: String,
 }
 
+impl CreateUser {
+    pub fn normalized(&self) -> CreateUser {
+        CreateUser {
+            nick: normalize_nick(&self.nick),
+            email: normalize_email(&self.email),
+        }
+    }
+}
+
+/// nicks are unique ignoring case, surrounding whitespace is dropped
+pub fn normalize_nick(nick: &str) -> String {
+    nick.trim().to_string()
+}
+
+/// emails are stored trimmed and lowercase
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Sorting
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListSort {
+    Id,
+    Title,
+    Created,
+    Updated,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl ListSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListSort::Id => "id",
+            ListSort::Title => "title",
+            ListSort::Created => "created",
+            ListSort::Updated => "updated",
+        }
+    }
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Filters for list collections
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    // only lists of this user, lists of all users when unset
+    pub owner_id: Option<i64>,
+    pub title_contains: Option<String>,
+    pub has_unchecked: Option<bool>,
+    // name of a tag the lists carry, case insensitive
+    pub tag: Option<String>,
+    // tags are per user, the tag is looked up among the tags of this user
+    pub tag_owner_id: Option<i64>,
+}
+
 /// Lists
 
-#[derive(Serialize, Debug, Clone, Queryable, Identifiable, Associations)]
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Identifiable, Associations)]
 #[belongs_to(User)]
 #[table_name = "lists"]
 pub struct List {
@@ -31,6 +118,20 @@ pub struct List {
     pub user_id: i64,
     pub title: String,
     pub subtitle: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    // missing in revisions recorded before templates existed
+    #[serde(default)]
+    pub is_template: bool,
+    #[serde(default)]
+    pub budget_amount: Option<BigDecimal>,
+    // ISO 4217 code, set exactly when there is a budget
+    #[serde(default)]
+    pub budget_currency: Option<String>,
+    // highest of BUDGET_THRESHOLDS the owner was notified about, kept out of responses and revisions
+    #[serde(default, skip_serializing)]
+    pub budget_notified_percent: Option<i16>,
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -39,18 +140,50 @@ pub struct CreateList {
     pub user_id: i64,
     pub title: String,
     pub subtitle: String,
+    pub is_template: bool,
+    pub budget_amount: Option<BigDecimal>,
+    pub budget_currency: Option<String>,
 }
 
 /// Items
 
-#[derive(Serialize, Debug, Clone, Queryable, Identifiable, Associations)]
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Identifiable, Associations)]
 #[belongs_to(List)]
 #[table_name = "items"]
 pub struct Item {
     pub id: i64,
     pub list_id: i64,
     pub title: String,
-    pub amount: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub checked: bool,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub checked_by: Option<i64>,
+    pub position: i64,
+    pub quantity: BigDecimal,
+    pub unit: Unit,
+    pub deleted_at: Option<DateTime<Utc>>,
+    // None for items at the top level of the list
+    #[serde(default)]
+    pub parent_item_id: Option<i64>,
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub remind_at: Option<DateTime<Utc>>,
+    // when the reminder was sent, cleared when remind_at changes
+    #[serde(default)]
+    pub reminded_at: Option<DateTime<Utc>>,
+    // markdown
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    // price of one of the item's unit
+    #[serde(default)]
+    pub unit_price: Option<BigDecimal>,
+    // ISO 4217 code, set exactly when there is a price
+    #[serde(default)]
+    pub currency: Option<String>,
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -58,5 +191,701 @@ pub struct Item {
 pub struct CreateItem {
     pub list_id: i64,
     pub title: String,
-    pub amount: i32,
+    pub quantity: BigDecimal,
+    pub unit: Unit,
+    pub parent_item_id: Option<i64>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    #[diesel(embed)]
+    pub details: ItemDetails,
+}
+
+/// Notes, link and price of an item, replaced as a whole
+#[derive(Debug, Clone, Default, Insertable, AsChangeset)]
+#[table_name = "items"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct ItemDetails {
+    pub notes: Option<String>,
+    pub url: Option<String>,
+    pub unit_price: Option<BigDecimal>,
+    pub currency: Option<String>,
+}
+
+/// the detail both have or the one that is set, None if they differ
+fn combine<T: Clone + PartialEq>(new: &Option<T>, existing: &Option<T>) -> Option<Option<T>> {
+    match (new, existing) {
+        (Some(new), Some(existing)) if new != existing => None,
+        (Some(_), None) => Some(new.clone()),
+        _ => Some(existing.clone()),
+    }
+}
+
+impl ItemDetails {
+    /// the details of `item` completed with these ones of an item in `unit` merged into it,
+    /// None if they contradict each other. A price only carries over to the same unit.
+    pub fn merged_into(&self, unit: Unit, item: &Item) -> Option<ItemDetails> {
+        if self.unit_price.is_some() && unit != item.unit {
+            return None;
+        }
+        let (unit_price, currency) = match &self.unit_price {
+            Some(_) => (
+                combine(&self.unit_price, &item.unit_price)?,
+                combine(&self.currency, &item.currency)?,
+            ),
+            None => (item.unit_price.clone(), item.currency.clone()),
+        };
+        Some(ItemDetails {
+            notes: combine(&self.notes, &item.notes)?,
+            url: combine(&self.url, &item.url)?,
+            unit_price,
+            currency,
+        })
+    }
+}
+
+/// An item with its sub-items, in list order
+#[derive(Serialize, Debug, Clone)]
+pub struct ItemNode {
+    #[serde(flatten)]
+    pub item: Item,
+    pub children: Vec<ItemNode>,
+}
+
+impl ItemNode {
+    /// nest the items of a list, ordered by position, under their parents.
+    /// Items whose parent is not among them end up at the top level.
+    pub fn tree(items: Vec<Item>) -> Vec<ItemNode> {
+        let ids: HashSet<i64> = items.iter().map(|item| item.id).collect();
+        let mut children: HashMap<i64, Vec<Item>> = HashMap::new();
+        let mut roots = Vec::new();
+        for item in items {
+            match item.parent_item_id {
+                Some(parent_id) if ids.contains(&parent_id) => {
+                    children.entry(parent_id).or_default().push(item)
+                }
+                _ => roots.push(item),
+            }
+        }
+
+        roots
+            .into_iter()
+            .map(|item| ItemNode::attach(item, &mut children))
+            .collect()
+    }
+
+    fn attach(item: Item, children: &mut HashMap<i64, Vec<Item>>) -> ItemNode {
+        let own_children = children.remove(&item.id).unwrap_or_default();
+        ItemNode {
+            children: own_children
+                .into_iter()
+                .map(|child| ItemNode::attach(child, children))
+                .collect(),
+            item,
+        }
+    }
+}
+
+/// Units
+
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow,
+)]
+#[sql_type = "Text"]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    #[default]
+    Pcs,
+    G,
+    Kg,
+    Ml,
+    L,
+    Packs,
+    Bottles,
+    Cans,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+}
+
+impl Unit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Pcs => "pcs",
+            Unit::G => "g",
+            Unit::Kg => "kg",
+            Unit::Ml => "ml",
+            Unit::L => "l",
+            Unit::Packs => "packs",
+            Unit::Bottles => "bottles",
+            Unit::Cans => "cans",
+        }
+    }
+
+    /// measurable units and how many base units (g, ml) one of them is
+    fn measure(&self) -> Option<(Dimension, u32)> {
+        match self {
+            Unit::G => Some((Dimension::Mass, 1)),
+            Unit::Kg => Some((Dimension::Mass, 1000)),
+            Unit::Ml => Some((Dimension::Volume, 1)),
+            Unit::L => Some((Dimension::Volume, 1000)),
+            _ => None,
+        }
+    }
+
+    /// express a quantity of this unit in another one, None if they can't be compared
+    pub fn convert(&self, quantity: &BigDecimal, to: Unit) -> Option<BigDecimal> {
+        if *self == to {
+            return Some(quantity.clone());
+        }
+        match (self.measure(), to.measure()) {
+            (Some((from_dimension, from_factor)), Some((to_dimension, to_factor)))
+                if from_dimension == to_dimension =>
+            {
+                Some(quantity * BigDecimal::from(from_factor) / BigDecimal::from(to_factor))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// quantities are stored as NUMERIC(12, 3) and have to stay below this
+pub fn max_quantity() -> BigDecimal {
+    BigDecimal::from(1_000_000_000)
+}
+
+impl ToSql<Text, Pg> for Unit {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for Unit {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"pcs" => Ok(Unit::Pcs),
+            b"g" => Ok(Unit::G),
+            b"kg" => Ok(Unit::Kg),
+            b"ml" => Ok(Unit::Ml),
+            b"l" => Ok(Unit::L),
+            b"packs" => Ok(Unit::Packs),
+            b"bottles" => Ok(Unit::Bottles),
+            b"cans" => Ok(Unit::Cans),
+            unknown => Err(format!("Unknown unit {:?}", String::from_utf8_lossy(unknown)).into()),
+        }
+    }
+}
+
+/// Completion of a list: n of m items checked
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub checked: i64,
+    pub total: i64,
+}
+
+impl Progress {
+    pub fn of_items(items: &[Item]) -> Progress {
+        Progress {
+            checked: items.iter().filter(|item| item.checked).count() as i64,
+            total: items.len() as i64,
+        }
+    }
+}
+
+/// Sums of price times quantity of a list's items in one currency, rounded to cents
+#[derive(Serialize, Debug, Clone)]
+pub struct CurrencyTotal {
+    pub currency: String,
+    pub checked: BigDecimal,
+    pub remaining: BigDecimal,
+    pub total: BigDecimal,
+}
+
+/// Totals of a list, one per currency as amounts in different currencies are never added up
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Totals {
+    // ordered by currency code
+    pub currencies: Vec<CurrencyTotal>,
+    // items without a price, they are not part of any total
+    pub unpriced: i64,
+}
+
+/// round a non negative amount half up to two decimals
+fn round_cents(amount: BigDecimal) -> BigDecimal {
+    (amount + BigDecimal::from(5) / BigDecimal::from(1000)).with_scale(2)
+}
+
+impl Totals {
+    pub fn of_items(items: &[Item]) -> Totals {
+        let mut sums: BTreeMap<String, (BigDecimal, BigDecimal)> = BTreeMap::new();
+        let mut unpriced = 0;
+        for item in items {
+            match (&item.unit_price, &item.currency) {
+                (Some(price), Some(currency)) => {
+                    let sum = sums
+                        .entry(currency.clone())
+                        .or_insert_with(|| (BigDecimal::from(0), BigDecimal::from(0)));
+                    let amount = price * &item.quantity;
+                    if item.checked {
+                        sum.0 += amount;
+                    } else {
+                        sum.1 += amount;
+                    }
+                }
+                _ => unpriced += 1,
+            }
+        }
+
+        Totals {
+            currencies: sums
+                .into_iter()
+                .map(|(currency, (checked, remaining))| {
+                    let checked = round_cents(checked);
+                    let remaining = round_cents(remaining);
+                    CurrencyTotal {
+                        currency,
+                        total: &checked + &remaining,
+                        checked,
+                        remaining,
+                    }
+                })
+                .collect(),
+            unpriced,
+        }
+    }
+}
+
+/// Budgets
+/// percentages of a budget the owner of the list is notified about when spending reaches them
+pub const BUDGET_THRESHOLDS: [i16; 2] = [80, 100];
+
+/// How much of a list's budget the checked items used
+#[derive(Serialize, Debug, Clone)]
+pub struct BudgetStatus {
+    pub amount: BigDecimal,
+    pub currency: String,
+    // checked items priced in the budget currency
+    pub spent: BigDecimal,
+    // negative once over budget
+    pub remaining: BigDecimal,
+    pub percent_used: BigDecimal,
+    pub over_budget: bool,
+    // currencies of checked items that are not counted against the budget
+    pub uncounted_currencies: Vec<String>,
+}
+
+impl BudgetStatus {
+    /// the status of the list's budget from the totals of its items, None without a budget
+    pub fn of_list(list: &List, totals: &Totals) -> Option<BudgetStatus> {
+        let (amount, currency) = match (&list.budget_amount, &list.budget_currency) {
+            (Some(amount), Some(currency)) => (amount.clone(), currency.clone()),
+            _ => return None,
+        };
+
+        let spent = totals
+            .currencies
+            .iter()
+            .find(|total| total.currency == currency)
+            .map_or_else(|| BigDecimal::from(0), |total| total.checked.clone());
+        let uncounted_currencies = totals
+            .currencies
+            .iter()
+            .filter(|total| total.currency != currency && total.checked > BigDecimal::from(0))
+            .map(|total| total.currency.clone())
+            .collect();
+        // the database keeps budgets above 0
+        let percent_used = if amount > BigDecimal::from(0) {
+            round_cents(&spent * BigDecimal::from(100) / &amount)
+        } else {
+            BigDecimal::from(0)
+        };
+
+        Some(BudgetStatus {
+            remaining: &amount - &spent,
+            over_budget: spent > amount,
+            amount,
+            currency,
+            spent,
+            percent_used,
+            uncounted_currencies,
+        })
+    }
+
+    /// the highest of BUDGET_THRESHOLDS that is reached, percent_used is rounded for display
+    /// so the exact spending is compared instead
+    pub fn reached_threshold(&self) -> Option<i16> {
+        let spent_percent = &self.spent * BigDecimal::from(100);
+        BUDGET_THRESHOLDS
+            .iter()
+            .rev()
+            .find(|threshold| spent_percent >= BigDecimal::from(**threshold) * &self.amount)
+            .cloned()
+    }
+}
+
+/// Notifications
+
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct PendingNotification {
+    pub id: i64,
+    pub user_id: i64,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    // set while a dispatcher is delivering it
+    pub claimed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "notifications"]
+pub struct CreateNotification {
+    pub user_id: i64,
+    pub payload: serde_json::Value,
+}
+
+/// Revisions
+
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct Revision {
+    pub revision: i64,
+    // None for the baseline recorded before the first tracked change
+    pub user_id: Option<i64>,
+    pub action: String,
+    pub diff: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+// a stored revision with its snapshot, as written to the export
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct StoredRevision {
+    pub id: i64,
+    pub list_id: i64,
+    pub revision: i64,
+    pub user_id: Option<i64>,
+    pub action: String,
+    pub diff: serde_json::Value,
+    pub snapshot: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "list_revisions"]
+pub struct CreateRevision {
+    pub list_id: i64,
+    pub revision: i64,
+    pub user_id: Option<i64>,
+    pub action: String,
+    pub diff: serde_json::Value,
+    pub snapshot: serde_json::Value,
+}
+
+/// Tags
+
+#[derive(Serialize, Debug, Clone, Queryable, Identifiable, Associations)]
+#[belongs_to(User)]
+#[table_name = "tags"]
+pub struct Tag {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "tags"]
+pub struct CreateTag {
+    pub user_id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "list_tags"]
+pub struct ListTag {
+    pub list_id: i64,
+    pub tag_id: i64,
+}
+
+/// Recurrences
+
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct Recurrence {
+    pub id: i64,
+    pub template_id: i64,
+    // RRULE subset, see recurrence::RecurrenceRule
+    pub rule: String,
+    pub starts_at: DateTime<Utc>,
+    // None once the rule has no further occurrences
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // why the list of the last occurrence could not be created
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset)]
+#[table_name = "recurrences"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CreateRecurrence {
+    pub template_id: i64,
+    pub rule: String,
+    pub starts_at: DateTime<Utc>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+/// Search
+
+#[derive(Serialize, Debug, Clone, QueryableByName)]
+pub struct SearchHit {
+    // "list" or "item"
+    #[sql_type = "Text"]
+    pub kind: String,
+    #[sql_type = "BigInt"]
+    pub id: i64,
+    // the list itself for list hits, the owning list for item hits
+    #[sql_type = "BigInt"]
+    pub list_id: i64,
+    #[sql_type = "Text"]
+    pub list_title: String,
+    #[sql_type = "Float4"]
+    pub rank: f32,
+    // HTML: the matched text escaped, with the hits wrapped in <mark></mark>
+    #[sql_type = "Text"]
+    pub snippet: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::str::FromStr;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn item(id: i64, parent_item_id: Option<i64>) -> Item {
+        let mut item = fixtures::item(id, &format!("item {}", id));
+        item.parent_item_id = parent_item_id;
+        item
+    }
+
+    #[test]
+    fn convert_keeps_quantity_of_same_unit() {
+        assert_eq!(
+            Unit::Pcs.convert(&decimal("3"), Unit::Pcs),
+            Some(decimal("3"))
+        );
+        assert_eq!(
+            Unit::Kg.convert(&decimal("1.5"), Unit::Kg),
+            Some(decimal("1.5"))
+        );
+    }
+
+    #[test]
+    fn convert_scales_within_a_dimension() {
+        assert_eq!(
+            Unit::G.convert(&decimal("250"), Unit::Kg),
+            Some(decimal("0.25"))
+        );
+        assert_eq!(
+            Unit::Kg.convert(&decimal("1.5"), Unit::G),
+            Some(decimal("1500"))
+        );
+        assert_eq!(
+            Unit::Ml.convert(&decimal("500"), Unit::L),
+            Some(decimal("0.5"))
+        );
+        assert_eq!(
+            Unit::L.convert(&decimal("0.75"), Unit::Ml),
+            Some(decimal("750"))
+        );
+    }
+
+    #[test]
+    fn convert_rejects_other_dimensions_and_counted_units() {
+        assert_eq!(Unit::G.convert(&decimal("1"), Unit::Ml), None);
+        assert_eq!(Unit::L.convert(&decimal("1"), Unit::Kg), None);
+        assert_eq!(Unit::Pcs.convert(&decimal("1"), Unit::Packs), None);
+        assert_eq!(Unit::Bottles.convert(&decimal("1"), Unit::L), None);
+    }
+
+    #[test]
+    fn merged_details_fill_in_what_the_item_lacks() {
+        let mut existing = item(1, None);
+        existing.notes = Some(String::from("organic"));
+        let new = ItemDetails {
+            notes: Some(String::from("organic")),
+            url: Some(String::from("https://example.com/milk")),
+            unit_price: Some(decimal("1.20")),
+            currency: Some(String::from("EUR")),
+        };
+
+        let merged = new.merged_into(Unit::Pcs, &existing).unwrap();
+
+        assert_eq!(merged.notes, Some(String::from("organic")));
+        assert_eq!(merged.url, Some(String::from("https://example.com/milk")));
+        assert_eq!(merged.unit_price, Some(decimal("1.2")));
+        assert_eq!(merged.currency, Some(String::from("EUR")));
+    }
+
+    #[test]
+    fn merged_details_refuse_conflicts() {
+        let mut existing = item(1, None);
+        existing.notes = Some(String::from("organic"));
+        existing.unit_price = Some(decimal("1.20"));
+        existing.currency = Some(String::from("EUR"));
+
+        let other_notes = ItemDetails {
+            notes: Some(String::from("lactose free")),
+            ..ItemDetails::default()
+        };
+        let other_price = ItemDetails {
+            unit_price: Some(decimal("0.99")),
+            currency: Some(String::from("EUR")),
+            ..ItemDetails::default()
+        };
+        let same_price = ItemDetails {
+            unit_price: Some(decimal("1.2")),
+            currency: Some(String::from("EUR")),
+            ..ItemDetails::default()
+        };
+
+        assert!(other_notes.merged_into(Unit::Pcs, &existing).is_none());
+        assert!(other_price.merged_into(Unit::Pcs, &existing).is_none());
+        assert!(same_price.merged_into(Unit::Pcs, &existing).is_some());
+        // a price per pack says nothing about the price per piece
+        assert!(same_price.merged_into(Unit::Packs, &existing).is_none());
+        assert!(ItemDetails::default()
+            .merged_into(Unit::Packs, &existing)
+            .is_some());
+    }
+
+    fn ids(nodes: &[ItemNode]) -> Vec<(i64, Vec<i64>)> {
+        nodes
+            .iter()
+            .map(|node| {
+                let children = node.children.iter().map(|child| child.item.id).collect();
+                (node.item.id, children)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tree_nests_items_under_their_parents_in_order() {
+        let tree = ItemNode::tree(vec![
+            item(1, None),
+            item(2, Some(1)),
+            item(3, None),
+            item(4, Some(1)),
+            item(5, Some(2)),
+        ]);
+
+        assert_eq!(ids(&tree), vec![(1, vec![2, 4]), (3, vec![])]);
+        assert_eq!(ids(&tree[0].children), vec![(2, vec![5]), (4, vec![])]);
+    }
+
+    #[test]
+    fn tree_puts_items_with_missing_parent_at_the_top_level() {
+        let tree = ItemNode::tree(vec![item(2, Some(1)), item(3, Some(2)), item(4, Some(9))]);
+
+        assert_eq!(ids(&tree), vec![(2, vec![3]), (4, vec![])]);
+    }
+
+    #[test]
+    fn tree_of_children_listed_before_their_parent() {
+        let tree = ItemNode::tree(vec![item(2, Some(1)), item(1, None)]);
+
+        assert_eq!(ids(&tree), vec![(1, vec![2])]);
+    }
+
+    fn priced(id: i64, quantity: &str, price: &str, currency: &str, checked: bool) -> Item {
+        let mut priced = item(id, None);
+        priced.quantity = decimal(quantity);
+        priced.unit_price = Some(decimal(price));
+        priced.currency = Some(String::from(currency));
+        priced.checked = checked;
+        priced
+    }
+
+    fn budget_status(amount: &str, items: &[Item]) -> BudgetStatus {
+        let mut list = fixtures::list(1, "Groceries");
+        list.budget_amount = Some(decimal(amount));
+        list.budget_currency = Some(String::from("EUR"));
+        BudgetStatus::of_list(&list, &Totals::of_items(items)).unwrap()
+    }
+
+    #[test]
+    fn threshold_is_reached_at_its_percentage() {
+        let spent = |price: &str| budget_status("50", &[priced(1, "1", price, "EUR", true)]);
+
+        assert_eq!(spent("39.99").reached_threshold(), None);
+        assert_eq!(spent("40").reached_threshold(), Some(80));
+        assert_eq!(spent("49.99").reached_threshold(), Some(80));
+        assert_eq!(spent("50").reached_threshold(), Some(100));
+        assert_eq!(spent("75").reached_threshold(), Some(100));
+        assert!(spent("75").over_budget);
+    }
+
+    #[test]
+    fn threshold_is_not_reached_by_rounding() {
+        let status = budget_status("10000", &[priced(1, "1", "7999.99", "EUR", true)]);
+
+        assert_eq!(status.percent_used, decimal("80.00"));
+        assert_eq!(status.reached_threshold(), None);
+    }
+
+    #[test]
+    fn threshold_counts_checked_items_in_the_budget_currency_only() {
+        let status = budget_status(
+            "50",
+            &[
+                priced(1, "2", "10", "EUR", true),
+                priced(2, "1", "100", "EUR", false),
+                priced(3, "1", "100", "USD", true),
+            ],
+        );
+
+        assert_eq!(status.percent_used, decimal("40.00"));
+        assert_eq!(status.reached_threshold(), None);
+        assert_eq!(status.uncounted_currencies, vec![String::from("USD")]);
+    }
+
+    #[test]
+    fn totals_round_half_up_to_cents() {
+        let totals = Totals::of_items(&[
+            priced(1, "0.25", "3.99", "EUR", true),
+            priced(2, "1", "0.005", "USD", true),
+            priced(3, "1", "0.004", "USD", false),
+        ]);
+
+        let eur = &totals.currencies[0];
+        assert_eq!(eur.currency, "EUR");
+        assert_eq!(eur.checked.to_string(), "1.00");
+        assert_eq!(eur.remaining.to_string(), "0.00");
+
+        let usd = &totals.currencies[1];
+        assert_eq!(usd.currency, "USD");
+        assert_eq!(usd.checked.to_string(), "0.01");
+        assert_eq!(usd.remaining.to_string(), "0.00");
+    }
+
+    #[test]
+    fn totals_add_up_the_rounded_sums() {
+        let totals = Totals::of_items(&[
+            priced(1, "3", "0.333", "EUR", true),
+            priced(2, "1", "0.005", "EUR", false),
+            priced(3, "2", "1.50", "EUR", false),
+            item(4, None),
+        ]);
+
+        let eur = &totals.currencies[0];
+        assert_eq!(eur.checked.to_string(), "1.00");
+        assert_eq!(eur.remaining.to_string(), "3.01");
+        assert_eq!(eur.total.to_string(), "4.01");
+        assert_eq!(totals.unpriced, 1);
+    }
 }
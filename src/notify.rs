@@ -0,0 +1,195 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::errors::{ApiError, ErrorType};
+use crate::models::{BudgetStatus, Item, List};
+
+/// A value that is delivered with a notification but never shows up in the log
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Something a user is told about outside of a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    // the remind_at time of an unchecked item has come
+    ItemReminder {
+        user_id: i64,
+        list_id: i64,
+        list_title: String,
+        item_id: i64,
+        item_title: String,
+        due_at: Option<DateTime<Utc>>,
+        remind_at: DateTime<Utc>,
+    },
+    // the checked items of a list used up a threshold percentage of its budget
+    BudgetThreshold {
+        user_id: i64,
+        list_id: i64,
+        list_title: String,
+        threshold_percent: i16,
+        amount: BigDecimal,
+        currency: String,
+        spent: BigDecimal,
+        over_budget: bool,
+    },
+    // a changed email address has to be confirmed with the token before it becomes active
+    EmailVerification {
+        user_id: i64,
+        email: String,
+        token: Secret,
+    },
+}
+
+impl Notification {
+    pub fn item_reminder(list: &List, item: &Item) -> Notification {
+        Notification::ItemReminder {
+            user_id: list.user_id,
+            list_id: list.id,
+            list_title: list.title.clone(),
+            item_id: item.id,
+            item_title: item.title.clone(),
+            due_at: item.due_at,
+            remind_at: item.remind_at.unwrap_or_else(Utc::now),
+        }
+    }
+
+    pub fn budget_threshold(list: &List, status: &BudgetStatus, threshold: i16) -> Notification {
+        Notification::BudgetThreshold {
+            user_id: list.user_id,
+            list_id: list.id,
+            list_title: list.title.clone(),
+            threshold_percent: threshold,
+            amount: status.amount.clone(),
+            currency: status.currency.clone(),
+            spent: status.spent.clone(),
+            over_budget: status.over_budget,
+        }
+    }
+
+    pub fn email_verification(user_id: i64, email: String, token: String) -> Notification {
+        Notification::EmailVerification {
+            user_id,
+            email,
+            token: Secret(token),
+        }
+    }
+
+    pub fn user_id(&self) -> i64 {
+        match self {
+            Notification::ItemReminder { user_id, .. } => *user_id,
+            Notification::BudgetThreshold { user_id, .. } => *user_id,
+            Notification::EmailVerification { user_id, .. } => *user_id,
+        }
+    }
+
+    /// the form notifications are queued in until they are delivered
+    pub fn to_json(&self) -> Result<serde_json::Value, ApiError> {
+        serde_json::to_value(self).map_err(|err| {
+            ApiError::new(
+                format!("while serializing notification: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })
+    }
+
+    pub fn from_json(value: serde_json::Value) -> Result<Notification, ApiError> {
+        serde_json::from_value(value).map_err(|err| {
+            ApiError::new(
+                format!("while reading notification: {}", err.to_string()).as_str(),
+                ErrorType::Internal,
+            )
+        })
+    }
+}
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send + 'a>>;
+
+/// Delivers notifications, picked once at startup
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a>;
+}
+
+/// Writes notifications to the log, the default without a webhook. Secrets like email tokens are left out,
+/// so email verification needs a webhook.
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            log::info!("notification: {:?}", notification);
+            Ok(())
+        })
+    }
+}
+
+/// POSTs every notification as JSON to a fixed http:// or https:// URL,
+/// e.g. a relay that forwards them to mail or push services
+pub struct WebhookNotifier {
+    url: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+/// how long a webhook call may take, well below the time a claimed notification is retried after
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<WebhookNotifier, String> {
+        let url: Uri = url.parse().map_err(|err| format!("{}", err))?;
+        match url.scheme_str() {
+            Some("http") | Some("https") => (),
+            _ => return Err(String::from("only http:// and https:// URLs are supported")),
+        }
+        Ok(WebhookNotifier {
+            url,
+            client: Client::builder().build(HttpsConnector::new()),
+        })
+    }
+}
+
+fn webhook_error(message: String) -> ApiError {
+    ApiError::new(
+        format!("while calling notification webhook: {}", message).as_str(),
+        ErrorType::Internal,
+    )
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let body =
+                serde_json::to_vec(notification).map_err(|err| webhook_error(err.to_string()))?;
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(self.url.clone())
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .map_err(|err| webhook_error(err.to_string()))?;
+
+            // a webhook that does not answer would hold up the notifications after it
+            let response = tokio::time::timeout(WEBHOOK_TIMEOUT, self.client.request(request))
+                .await
+                .map_err(|_| webhook_error(format!("no response within {:?}", WEBHOOK_TIMEOUT)))?
+                .map_err(|err| webhook_error(err.to_string()))?;
+            if !response.status().is_success() {
+                return Err(webhook_error(format!("status {}", response.status())));
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::{ApiError, ErrorType};
+use crate::models::{List, ListSort, SortOrder};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Value of the sort column of the last row on a page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum CursorValue {
+    Id,
+    Title(String),
+    Timestamp(DateTime<Utc>),
+}
+
+/// Position after which the next page starts. Handed to clients as an opaque string,
+/// it only stays valid for the sort and order it was created with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cursor {
+    pub sort: ListSort,
+    pub order: SortOrder,
+    pub value: CursorValue,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn after_list(list: &List, sort: ListSort, order: SortOrder) -> Cursor {
+        let value = match sort {
+            ListSort::Id => CursorValue::Id,
+            ListSort::Title => CursorValue::Title(list.title.clone()),
+            ListSort::Created => CursorValue::Timestamp(list.created_at),
+            ListSort::Updated => CursorValue::Timestamp(list.updated_at),
+        };
+        Cursor {
+            sort,
+            order,
+            value,
+            id: list.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        // serializing plain data can not fail
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// decode a cursor from the query string, it has to match the requested sort and order
+    pub fn decode(encoded: &str, sort: ListSort, order: SortOrder) -> Result<Cursor, ApiError> {
+        let invalid = || ApiError::new("cursor: invalid cursor", ErrorType::BadRequest);
+
+        let json =
+            base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        let cursor: Cursor = serde_json::from_slice(&json).map_err(|_| invalid())?;
+
+        if cursor.sort != sort || cursor.order != order {
+            return Err(ApiError::new(
+                "cursor: was created for a different sort or order",
+                ErrorType::BadRequest,
+            ));
+        }
+        // an edited cursor could otherwise page by another column than the lists are sorted by
+        let fits_sort = matches!(
+            (&cursor.value, sort),
+            (CursorValue::Id, ListSort::Id)
+                | (CursorValue::Title(_), ListSort::Title)
+                | (CursorValue::Timestamp(_), ListSort::Created)
+                | (CursorValue::Timestamp(_), ListSort::Updated)
+        );
+        if !fits_sort {
+            return Err(invalid());
+        }
+        Ok(cursor)
+    }
+}
+
+/// Response envelope for paginated collections
+#[derive(Serialize, Debug, Clone)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub next: Option<String>,
+}
+
+/// clamp the requested page size into the allowed range
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// percent-encode a value for use in a query string of a next link
+pub fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn list() -> List {
+        let mut list = fixtures::list(42, "Groceries & more");
+        list.updated_at = "2021-05-11T12:30:00Z".parse().unwrap();
+        list
+    }
+
+    fn encode_json(value: serde_json::Value) -> String {
+        base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn cursor_survives_encoding() {
+        let cursor = Cursor::after_list(&list(), ListSort::Title, SortOrder::Desc);
+
+        let decoded = Cursor::decode(&cursor.encode(), ListSort::Title, SortOrder::Desc).unwrap();
+
+        assert_eq!(decoded.id, 42);
+        match decoded.value {
+            CursorValue::Title(title) => assert_eq!(title, "Groceries & more"),
+            other => panic!("unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cursor_keeps_the_timestamp_of_the_sort() {
+        let cursor = Cursor::after_list(&list(), ListSort::Updated, SortOrder::Asc);
+
+        let decoded = Cursor::decode(&cursor.encode(), ListSort::Updated, SortOrder::Asc).unwrap();
+
+        match decoded.value {
+            CursorValue::Timestamp(at) => assert_eq!(at, list().updated_at),
+            other => panic!("unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cursor_only_fits_its_sort_and_order() {
+        let encoded = Cursor::after_list(&list(), ListSort::Id, SortOrder::Asc).encode();
+
+        assert!(Cursor::decode(&encoded, ListSort::Id, SortOrder::Desc).is_err());
+        assert!(Cursor::decode(&encoded, ListSort::Title, SortOrder::Asc).is_err());
+    }
+
+    #[test]
+    fn tampered_cursors_are_rejected() {
+        let encoded = Cursor::after_list(&list(), ListSort::Id, SortOrder::Asc).encode();
+
+        for tampered in &[
+            String::from("not a cursor!"),
+            encoded[..encoded.len() - 4].to_string(),
+            encode_json(json!({"sort": "id", "order": "asc"})),
+            encode_json(json!({"sort": "id", "order": "asc", "value": {"Title": "x"}, "id": 1})),
+            encode_json(json!({"sort": "id", "order": "asc", "value": "Id", "id": "1"})),
+        ] {
+            assert!(
+                Cursor::decode(tampered, ListSort::Id, SortOrder::Asc).is_err(),
+                "{}",
+                tampered
+            );
+        }
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(1000)), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn query_values_are_percent_encoded() {
+        assert_eq!(encode_query_value("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(encode_query_value("plain-text_1.0~"), "plain-text_1.0~");
+    }
+}
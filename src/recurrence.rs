@@ -0,0 +1,378 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::{ApiError, ErrorType};
+
+/// largest accepted INTERVAL, keeps the search for the next occurrence short
+const MAX_INTERVAL: u32 = 52;
+
+/// days searched per interval step before giving up on finding another occurrence
+const SEARCH_DAYS_PER_INTERVAL: u32 = 400;
+
+fn one() -> u32 {
+    1
+}
+
+/// Weekdays as written in BYDAY
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Day {
+    Mo,
+    Tu,
+    We,
+    Th,
+    Fr,
+    Sa,
+    Su,
+}
+
+impl Day {
+    fn weekday(&self) -> Weekday {
+        match self {
+            Day::Mo => Weekday::Mon,
+            Day::Tu => Weekday::Tue,
+            Day::We => Weekday::Wed,
+            Day::Th => Weekday::Thu,
+            Day::Fr => Weekday::Fri,
+            Day::Sa => Weekday::Sat,
+            Day::Su => Weekday::Sun,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Day::Mo => "MO",
+            Day::Tu => "TU",
+            Day::We => "WE",
+            Day::Th => "TH",
+            Day::Fr => "FR",
+            Day::Sa => "SA",
+            Day::Su => "SU",
+        }
+    }
+
+    fn parse(day: &str) -> Option<Day> {
+        match day {
+            "MO" => Some(Day::Mo),
+            "TU" => Some(Day::Tu),
+            "WE" => Some(Day::We),
+            "TH" => Some(Day::Th),
+            "FR" => Some(Day::Fr),
+            "SA" => Some(Day::Sa),
+            "SU" => Some(Day::Su),
+            _ => None,
+        }
+    }
+}
+
+/// When a template repeats, the subset of the iCalendar RRULE with FREQ (daily, weekly or monthly),
+/// INTERVAL, BYDAY and BYMONTHDAY. Occurrences fall on the time of day of the start, in UTC.
+/// Without weekdays or a day of the month the ones of the start are used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "frequency", rename_all = "snake_case")]
+pub enum RecurrenceRule {
+    Daily {
+        #[serde(default = "one")]
+        interval: u32,
+    },
+    Weekly {
+        #[serde(default = "one")]
+        interval: u32,
+        #[serde(default)]
+        weekdays: Vec<Day>,
+    },
+    Monthly {
+        #[serde(default = "one")]
+        interval: u32,
+        month_day: Option<u32>,
+    },
+}
+
+fn invalid(message: String) -> ApiError {
+    ApiError::new(format!("rule: {}", message).as_str(), ErrorType::BadRequest)
+}
+
+impl RecurrenceRule {
+    fn interval(&self) -> u32 {
+        match self {
+            RecurrenceRule::Daily { interval } => *interval,
+            RecurrenceRule::Weekly { interval, .. } => *interval,
+            RecurrenceRule::Monthly { interval, .. } => *interval,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.interval() < 1 || self.interval() > MAX_INTERVAL {
+            return Err(invalid(format!(
+                "interval must be between 1 and {}",
+                MAX_INTERVAL
+            )));
+        }
+        if let RecurrenceRule::Monthly {
+            month_day: Some(day),
+            ..
+        } = self
+        {
+            if *day < 1 || *day > 31 {
+                return Err(invalid(String::from("month_day must be between 1 and 31")));
+            }
+        }
+        Ok(())
+    }
+
+    /// whether the rule started on `start` has an occurrence on the date
+    fn matches(&self, start: NaiveDate, date: NaiveDate) -> bool {
+        let interval = self.interval() as i64;
+        match self {
+            RecurrenceRule::Daily { .. } => (date - start).num_days() % interval == 0,
+            RecurrenceRule::Weekly { weekdays, .. } => {
+                let on_weekday = if weekdays.is_empty() {
+                    date.weekday() == start.weekday()
+                } else {
+                    weekdays.iter().any(|day| day.weekday() == date.weekday())
+                };
+                // weeks start on monday, counted from the week of the start
+                let week_of = |day: NaiveDate| {
+                    day - Duration::days(day.weekday().num_days_from_monday() as i64)
+                };
+                let weeks = (week_of(date) - week_of(start)).num_days() / 7;
+                on_weekday && weeks % interval == 0
+            }
+            RecurrenceRule::Monthly { month_day, .. } => {
+                let months = (date.year() - start.year()) as i64 * 12 + date.month() as i64
+                    - start.month() as i64;
+                date.day() == month_day.unwrap_or(start.day()) && months % interval == 0
+            }
+        }
+    }
+
+    /// the first occurrence strictly after `after` of the rule started at `start`,
+    /// None if there is none, e.g. every 12 months on the 31st starting in February
+    pub fn next_after(&self, start: DateTime<Utc>, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start_date = start.naive_utc().date();
+        let mut date = std::cmp::max(start_date, after.naive_utc().date());
+
+        for _ in 0..self.interval() * SEARCH_DAYS_PER_INTERVAL {
+            if self.matches(start_date, date) {
+                let occurrence = DateTime::<Utc>::from_utc(date.and_time(start.time()), Utc);
+                if occurrence > after {
+                    return Some(occurrence);
+                }
+            }
+            date = date.succ();
+        }
+        None
+    }
+
+    /// the first occurrence at or after the start that is still ahead of `now`
+    pub fn first_after(&self, start: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_after(start, std::cmp::max(now, start - Duration::seconds(1)))
+    }
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecurrenceRule::Daily { interval } => write!(f, "FREQ=DAILY;INTERVAL={}", interval),
+            RecurrenceRule::Weekly { interval, weekdays } => {
+                write!(f, "FREQ=WEEKLY;INTERVAL={}", interval)?;
+                if !weekdays.is_empty() {
+                    let days: Vec<&str> = weekdays.iter().map(Day::as_str).collect();
+                    write!(f, ";BYDAY={}", days.join(","))?;
+                }
+                Ok(())
+            }
+            RecurrenceRule::Monthly {
+                interval,
+                month_day,
+            } => {
+                write!(f, "FREQ=MONTHLY;INTERVAL={}", interval)?;
+                if let Some(day) = month_day {
+                    write!(f, ";BYMONTHDAY={}", day)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = ApiError;
+
+    /// parse an RRULE like `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH`, the `RRULE:` prefix is optional
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut weekdays = Vec::new();
+        let mut month_day = None;
+
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let mut key_value = part.splitn(2, '=');
+            let key = key_value.next().unwrap_or_default().to_uppercase();
+            let value = key_value.next().unwrap_or_default().to_uppercase();
+
+            match key.as_str() {
+                "FREQ" => frequency = Some(value),
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid INTERVAL {}", value)))?
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        weekdays.push(
+                            Day::parse(day)
+                                .ok_or_else(|| invalid(format!("invalid BYDAY {}", day)))?,
+                        );
+                    }
+                }
+                "BYMONTHDAY" => {
+                    month_day = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(format!("invalid BYMONTHDAY {}", value)))?,
+                    )
+                }
+                _ => return Err(invalid(format!("unsupported part {}", part))),
+            }
+        }
+
+        let parsed = match frequency.as_deref() {
+            Some("DAILY") if weekdays.is_empty() && month_day.is_none() => {
+                RecurrenceRule::Daily { interval }
+            }
+            Some("WEEKLY") if month_day.is_none() => RecurrenceRule::Weekly { interval, weekdays },
+            Some("MONTHLY") if weekdays.is_empty() => RecurrenceRule::Monthly {
+                interval,
+                month_day,
+            },
+            Some("DAILY") | Some("WEEKLY") | Some("MONTHLY") => {
+                return Err(invalid(String::from(
+                    "BYDAY only works with WEEKLY and BYMONTHDAY only with MONTHLY",
+                )))
+            }
+            _ => {
+                return Err(invalid(String::from(
+                    "FREQ must be DAILY, WEEKLY or MONTHLY",
+                )))
+            }
+        };
+        parsed.validate()?;
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_rrule_and_displays_it_back() {
+        let rule: RecurrenceRule = "RRULE:freq=weekly;interval=2;byday=MO,TH".parse().unwrap();
+
+        assert_eq!(
+            rule,
+            RecurrenceRule::Weekly {
+                interval: 2,
+                weekdays: vec![Day::Mo, Day::Th],
+            }
+        );
+        assert_eq!(rule.to_string(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH");
+        assert_eq!(rule.to_string().parse::<RecurrenceRule>().unwrap(), rule);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in &[
+            "",
+            "FREQ=YEARLY",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=WEEKLY;BYMONTHDAY=3",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=53",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=DAILY;COUNT=3",
+        ] {
+            assert!(rule.parse::<RecurrenceRule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn daily_rule_keeps_interval_and_time_of_start() {
+        let rule = RecurrenceRule::Daily { interval: 3 };
+
+        assert_eq!(
+            rule.next_after(at("2021-07-01T08:00:00Z"), at("2021-07-02T00:00:00Z")),
+            Some(at("2021-07-04T08:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn weekly_rule_skips_weeks_between_intervals() {
+        // 2021-07-01 is a thursday
+        let rule = RecurrenceRule::Weekly {
+            interval: 2,
+            weekdays: vec![Day::Mo, Day::Th],
+        };
+        let start = at("2021-07-01T08:00:00Z");
+
+        assert_eq!(
+            rule.next_after(start, start),
+            Some(at("2021-07-12T08:00:00Z"))
+        );
+        assert_eq!(
+            rule.next_after(start, at("2021-07-12T08:00:00Z")),
+            Some(at("2021-07-15T08:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn monthly_rule_skips_months_without_the_day() {
+        let rule = RecurrenceRule::Monthly {
+            interval: 1,
+            month_day: Some(31),
+        };
+        let start = at("2021-01-31T10:00:00Z");
+
+        assert_eq!(
+            rule.next_after(start, start),
+            Some(at("2021-03-31T10:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn monthly_rule_without_possible_occurrence_has_none() {
+        let rule = RecurrenceRule::Monthly {
+            interval: 12,
+            month_day: Some(31),
+        };
+        let start = at("2021-02-10T10:00:00Z");
+
+        assert_eq!(rule.next_after(start, start), None);
+    }
+
+    #[test]
+    fn first_occurrence_is_the_start_unless_it_passed() {
+        let rule = RecurrenceRule::Daily { interval: 1 };
+        let start = at("2021-07-01T08:00:00Z");
+
+        assert_eq!(
+            rule.first_after(start, at("2021-06-01T00:00:00Z")),
+            Some(start)
+        );
+        assert_eq!(
+            rule.first_after(start, at("2021-07-05T12:00:00Z")),
+            Some(at("2021-07-06T08:00:00Z"))
+        );
+    }
+}
@@ -1,27 +1,49 @@
-use crate::api;
+use crate::api::{self, TrashKind};
+use crate::auth::{self, Auth, Scope};
+use crate::webauthn::actors::WebauthnActor;
+use crate::webauthn::routes::with_webauthn_actor;
 use crate::with_db_access_manager;
 use crate::with_json_body;
 use crate::PgPool;
 
+use std::sync::Arc;
 use warp::Filter;
 
-/// Admin: Get all lists
-/// GET /lists
+/// Get the lists of the caller, admins get the lists of all users
+/// GET /lists?include=items|summary&sort=id|title|created|updated&order=asc|desc&limit=&cursor=&title=&has_unchecked=&tag=
 pub fn get_lists(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("lists")
         .and(warp::get())
+        .and(warp::query::<api::ListsQuery>())
+        .and(auth::with_scope(auth, Scope::ListsRead))
         .and(with_db_access_manager(pool))
         .and_then(api::get_lists)
 }
 
+/// GET /search?q=&limit=
+pub fn search(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("search")
+        .and(warp::get())
+        .and(warp::query::<api::SearchQuery>())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::search)
+}
+
 /// GET /list/:id
 pub fn get_list(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("list" / i64)
         .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
         .and(with_db_access_manager(pool))
         .and_then(api::get_list)
 }
@@ -29,9 +51,11 @@ pub fn get_list(
 /// POST /list
 pub fn add_list(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("list") // Match /lists path
         .and(warp::post()) // Match POST method
+        .and(auth::with_scope(auth, Scope::ListsWrite))
         .and(with_db_access_manager(pool)) // Add DBAccessManager to params tuple
         .and(with_json_body::<api::AddList>()) // Try to deserialize JSON body to AddList
         .and_then(api::add_list) // Pass the params touple to the handler function
@@ -40,9 +64,11 @@ pub fn add_list(
 /// PUT /list/:id/
 pub fn update_list(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("list" / i64)
         .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
         .and(with_db_access_manager(pool))
         .and(with_json_body::<api::AddList>()) // Try to deserialize JSON body to AddList
         .and_then(api::update_list)
@@ -51,41 +77,519 @@ pub fn update_list(
 /// DELETE /list/:id
 pub fn delete_list(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("list" / i64)
         .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
         .and(with_db_access_manager(pool))
         .and_then(api::delete_list)
 }
 
+/// PUT /list/:id/order
+pub fn reorder_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "order")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::ReorderItems>())
+        .and_then(api::reorder_items)
+}
+
+/// DELETE /list/:id/checked
+pub fn clear_checked_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "checked")
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::clear_checked_items)
+}
+
 /// POST /item
 pub fn add_item(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("item") // Match /item path
         .and(warp::post()) // Match POST method
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
         .and(with_db_access_manager(pool)) // Add DBManager to params tuple
         .and(with_json_body::<api::AddItem>()) // Try to deserialize JSON body to AddList
         .and_then(api::add_item) // Pass the params touple to the handler function
 }
 
+/// POST /list/:id/items:batch
+pub fn batch_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "items:batch")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::BatchItems>())
+        .and_then(api::batch_items)
+}
+
 /// PUT /item/:id/
 pub fn update_item(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("item" / i64)
         .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
         .and(with_db_access_manager(pool))
         .and(with_json_body::<api::UpdateItem>()) // Try to deserialize JSON body to AddList
         .and_then(api::update_item)
 }
 
+/// PUT /item/:id/parent
+pub fn set_item_parent(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "parent")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::SetParent>())
+        .and_then(api::set_item_parent)
+}
+
+/// PUT /item/:id/details
+pub fn set_item_details(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "details")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::SetDetails>())
+        .and_then(api::set_item_details)
+}
+
+/// PUT /item/:id/schedule
+pub fn set_item_schedule(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "schedule")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::SetSchedule>())
+        .and_then(api::set_item_schedule)
+}
+
+/// GET /due?before=&limit=
+pub fn get_due_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("due")
+        .and(warp::get())
+        .and(warp::query::<api::DueQuery>())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_due_items)
+}
+
+/// POST /item/:id/toggle?cascade=
+pub fn toggle_item(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "toggle")
+        .and(warp::post())
+        .and(warp::query::<api::ToggleQuery>())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::toggle_item)
+}
+
+/// POST /item/:id/move
+pub fn move_item(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "move")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TransferItem>())
+        .and_then(api::move_item)
+}
+
+/// POST /item/:id/copy
+pub fn copy_item(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / i64 / "copy")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TransferItem>())
+        .and_then(api::copy_item)
+}
+
+/// POST /items:move
+pub fn move_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("items:move")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TransferItems>())
+        .and_then(api::move_items)
+}
+
+/// POST /items:copy
+pub fn copy_items(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("items:copy")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TransferItems>())
+        .and_then(api::copy_items)
+}
+
 /// DELETE /item/:id
 pub fn delete_item(
     pool: PgPool,
+    auth: Arc<Auth>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("item" / i64)
         .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ItemsWrite))
         .and(with_db_access_manager(pool))
         .and_then(api::delete_item)
 }
+
+/// PUT /list/:id/budget
+pub fn set_list_budget(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "budget")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::SetBudget>())
+        .and_then(api::set_list_budget)
+}
+
+/// DELETE /list/:id/budget
+pub fn delete_list_budget(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "budget")
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::delete_list_budget)
+}
+
+/// GET /tags
+pub fn get_tags(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("tags")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_tags)
+}
+
+/// POST /tags
+pub fn add_tag(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("tags")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TagName>())
+        .and_then(api::add_tag)
+}
+
+/// PUT /tag/:id
+pub fn rename_tag(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("tag" / i64)
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::TagName>())
+        .and_then(api::rename_tag)
+}
+
+/// DELETE /tag/:id
+pub fn delete_tag(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("tag" / i64)
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::delete_tag)
+}
+
+/// GET /list/:id/tags
+pub fn get_list_tags(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "tags")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_list_tags)
+}
+
+/// PUT /list/:id/tags/:tag_id
+pub fn tag_list(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "tags" / i64)
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::tag_list)
+}
+
+/// DELETE /list/:id/tags/:tag_id
+pub fn untag_list(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "tags" / i64)
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::untag_list)
+}
+
+/// POST /list/:id/clone
+pub fn clone_list(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "clone")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::CloneList>())
+        .and_then(api::clone_list)
+}
+
+/// GET /templates
+pub fn get_templates(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("templates")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_templates)
+}
+
+/// POST /template/:id/instantiate
+pub fn instantiate_template(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("template" / i64 / "instantiate")
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::InstantiateTemplate>())
+        .and_then(api::instantiate_template)
+}
+
+/// PUT /template/:id/recurrence
+pub fn set_recurrence(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("template" / i64 / "recurrence")
+        .and(warp::put())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::SetRecurrence>())
+        .and_then(api::set_recurrence)
+}
+
+/// GET /template/:id/recurrence
+pub fn get_recurrence(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("template" / i64 / "recurrence")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_recurrence)
+}
+
+/// DELETE /template/:id/recurrence
+pub fn delete_recurrence(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("template" / i64 / "recurrence")
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::delete_recurrence)
+}
+
+/// GET /list/:id/history?before=&limit=
+pub fn get_history(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "history")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(warp::query::<api::HistoryQuery>())
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_history)
+}
+
+/// POST /list/:id/revert/:revision
+pub fn revert_list(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / i64 / "revert" / i64)
+        .and(warp::post())
+        .and(auth::with_scope(auth, Scope::ListsWrite))
+        .and(with_db_access_manager(pool))
+        .and_then(api::revert_list)
+}
+
+/// GET /trash
+pub fn get_trash(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("trash")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::ListsRead))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_trash)
+}
+
+/// POST /trash/:kind/:id/restore
+pub fn restore(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("trash" / TrashKind / i64 / "restore")
+        .and(warp::post())
+        .and(auth::with_claims(auth)) // The required scope depends on the kind, checked in the handler
+        .and(with_db_access_manager(pool))
+        .and_then(api::restore)
+}
+
+/// GET /me
+pub fn get_me(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me")
+        .and(warp::get())
+        .and(auth::with_claims(auth))
+        .and(with_db_access_manager(pool))
+        .and_then(api::get_me)
+}
+
+/// PUT /me
+pub fn update_me(
+    pool: PgPool,
+    auth: Arc<Auth>,
+    actor: Arc<WebauthnActor>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me")
+        .and(warp::put())
+        .and(auth::with_scope(auth.clone(), Scope::Profile)) // Only tokens with the profile scope may change it
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::UpdateMe>())
+        .and(auth::with_auth(auth))
+        .and(with_webauthn_actor(actor))
+        .and_then(api::update_me)
+}
+
+/// POST /me/email/verify
+pub fn verify_email(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "email" / "verify")
+        .and(warp::post())
+        .and(auth::with_claims(auth.clone()))
+        .and(with_db_access_manager(pool))
+        .and(with_json_body::<api::VerifyEmail>())
+        .and(auth::with_auth(auth))
+        .and_then(api::verify_email)
+}
+
+/// GET /me/export
+pub fn export_me(
+    pool: PgPool,
+    auth: Arc<Auth>,
+    actor: Arc<WebauthnActor>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me" / "export")
+        .and(warp::get())
+        .and(auth::with_scope(auth, Scope::Profile))
+        .and(with_db_access_manager(pool))
+        .and(with_webauthn_actor(actor))
+        .and_then(api::export_me)
+}
+
+/// DELETE /me
+pub fn delete_me(
+    pool: PgPool,
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("me")
+        .and(warp::delete())
+        .and(auth::with_scope(auth, Scope::Profile))
+        .and(with_db_access_manager(pool))
+        .and_then(api::delete_me)
+}
+
+/// POST /token
+pub fn create_token(
+    auth: Arc<Auth>,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("token")
+        .and(warp::post())
+        .and(auth::with_claims(auth.clone())) // Any valid token may derive a narrower one
+        .and(with_json_body::<api::CreateToken>())
+        .and(auth::with_auth(auth))
+        .and_then(api::create_token)
+}
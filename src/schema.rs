@@ -3,7 +3,43 @@ table! {
         id -> Int8,
         list_id -> Int8,
         title -> Varchar,
-        amount -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        checked -> Bool,
+        checked_at -> Nullable<Timestamptz>,
+        checked_by -> Nullable<Int8>,
+        position -> Int8,
+        quantity -> Numeric,
+        unit -> Varchar,
+        deleted_at -> Nullable<Timestamptz>,
+        parent_item_id -> Nullable<Int8>,
+        due_at -> Nullable<Timestamptz>,
+        remind_at -> Nullable<Timestamptz>,
+        reminded_at -> Nullable<Timestamptz>,
+        notes -> Nullable<Text>,
+        url -> Nullable<Varchar>,
+        unit_price -> Nullable<Numeric>,
+        currency -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    list_revisions (id) {
+        id -> Int8,
+        list_id -> Int8,
+        revision -> Int8,
+        user_id -> Nullable<Int8>,
+        action -> Varchar,
+        diff -> Jsonb,
+        snapshot -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    list_tags (list_id, tag_id) {
+        list_id -> Int8,
+        tag_id -> Int8,
     }
 }
 
@@ -13,6 +49,47 @@ table! {
         user_id -> Int8,
         title -> Varchar,
         subtitle -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        deleted_at -> Nullable<Timestamptz>,
+        is_template -> Bool,
+        budget_amount -> Nullable<Numeric>,
+        budget_currency -> Nullable<Varchar>,
+        budget_notified_percent -> Nullable<Int2>,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Int8,
+        user_id -> Int8,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+        claimed_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    recurrences (id) {
+        id -> Int8,
+        template_id -> Int8,
+        rule -> Varchar,
+        starts_at -> Timestamptz,
+        next_run_at -> Nullable<Timestamptz>,
+        last_run_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_error -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Int8,
+        user_id -> Int8,
+        name -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -21,7 +98,30 @@ table! {
         id -> Int8,
         nick -> Varchar,
         email -> Varchar,
+        pending_email -> Nullable<Varchar>,
+        deletion_requested_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
-allow_tables_to_appear_in_same_query!(items, lists, users,);
+joinable!(items -> lists (list_id));
+joinable!(list_revisions -> lists (list_id));
+joinable!(list_revisions -> users (user_id));
+joinable!(list_tags -> lists (list_id));
+joinable!(list_tags -> tags (tag_id));
+joinable!(lists -> users (user_id));
+joinable!(notifications -> users (user_id));
+joinable!(recurrences -> lists (template_id));
+joinable!(tags -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(
+    items,
+    list_revisions,
+    list_tags,
+    lists,
+    notifications,
+    recurrences,
+    tags,
+    users,
+);
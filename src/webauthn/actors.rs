@@ -1,3 +1,4 @@
+use webauthn_rs::base64_data::Base64UrlSafeData;
 use webauthn_rs::ephemeral::WebauthnEphemeralConfig;
 use webauthn_rs::error::WebauthnError;
 use webauthn_rs::proto::{
@@ -8,15 +9,30 @@ use webauthn_rs::{AuthenticationState, RegistrationState, Webauthn};
 
 use async_std::sync::Mutex;
 use lru::LruCache;
+use serde_derive::Serialize;
 use std::collections::BTreeMap;
 
 use crate::db;
-use crate::models::CreateUser;
+use crate::errors::ApiError;
+use crate::models::{normalize_nick, CreateUser};
 
 type WebauthnResult<T> = core::result::Result<T, WebauthnError>;
 
 const CHALLENGE_CACHE_SIZE: usize = 256;
 
+/// challenges and credentials are kept per nick, ignoring case like the unique index on users
+fn credential_key(nick: &str) -> UserId {
+    normalize_nick(nick).to_lowercase().into_bytes()
+}
+
+/// Public, non-secret information about a registered credential
+#[derive(Serialize, Debug, Clone)]
+pub struct CredentialMetadata {
+    pub cred_id: Base64UrlSafeData,
+    pub counter: u32,
+    pub verified: bool,
+}
+
 pub struct WebauthnActor {
     wan: Webauthn<WebauthnEphemeralConfig>,
     reg_chals: Mutex<LruCache<UserId, RegistrationState>>,
@@ -39,11 +55,12 @@ impl WebauthnActor {
         &self,
         nick: String,
     ) -> WebauthnResult<CreationChallengeResponse> {
+        let nick = normalize_nick(&nick);
         log::info!("Webauthn: Challenge Register -> {:?}", nick);
         let (ccr, rs) = self
             .wan
             .generate_challenge_register(&nick, Some(UserVerificationPolicy::Discouraged))?;
-        self.reg_chals.lock().await.put(nick.into_bytes(), rs);
+        self.reg_chals.lock().await.put(credential_key(&nick), rs);
         log::info!("Webauthn: Challenge Register Complete -> {:?}", ccr);
 
         return Ok(ccr);
@@ -54,7 +71,8 @@ impl WebauthnActor {
         user: CreateUser,
         reg: RegisterPublicKeyCredential,
         db_manager: db::DBManager,
-    ) -> WebauthnResult<i64> {
+    ) -> Result<i64, ApiError> {
+        let user = user.normalized();
         log::info!(
             "handle Register -> (nick: {:?}, email: {:?}, reg: {:?})",
             user.nick,
@@ -62,55 +80,35 @@ impl WebauthnActor {
             reg
         );
 
-        // check if a user with this email already exists in the database
-        let result = db_manager.get_user_by_email(user.email.clone());
-        let registered_user = match result {
-            Ok(existing_user) => existing_user,
-            Err(_) => {
-                // if not, create a new user with the username
-                match db_manager.create_user(user.clone()) {
-                    Ok(new_user) => new_user,
-                    Err(_) => Err(WebauthnError::InvalidUsername)?,
-                }
-            }
-        };
-
-        let username = user.nick.as_bytes().to_vec();
+        let username = credential_key(&user.nick);
 
-        let rs = self
-            .reg_chals
-            .lock()
-            .await
-            .pop(&username)
-            .ok_or(WebauthnError::ChallengeNotFound)?;
+        let rs = self.reg_chals.lock().await.pop(&username).ok_or_else(|| {
+            ApiError::from_webauthn_error(WebauthnError::ChallengeNotFound, "register")
+        })?;
 
         // TODO: add new credential to the database, replace the code below
 
-        let mut creds = self.creds.lock().await;
-        let r = match creds.get_mut(&username) {
-            Some(ucreds) => self
-                .wan
-                .register_credential(&reg, rs, |cred_id| Ok(ucreds.contains_key(cred_id)))
-                .map(|cred| {
-                    let cred_id = cred.cred_id.clone();
-                    ucreds.insert(cred_id, cred);
-                }),
-            None => {
-                let r = self
-                    .wan
-                    .register_credential(&reg, rs, |_| Ok(false))
-                    .map(|cred| {
-                        let mut t = BTreeMap::new();
-                        let credential_id = cred.cred_id.clone();
-                        t.insert(credential_id, cred);
-                        creds.insert(username, t);
-                    });
-                log::debug!("{:?}", self.creds);
-                r
-            }
-        };
+        // the credential has to check out before an account is created for it
+        let cred = {
+            let creds = self.creds.lock().await;
+            self.wan.register_credential(&reg, rs, |cred_id| {
+                Ok(creds
+                    .get(&username)
+                    .is_some_and(|ucreds| ucreds.contains_key(cred_id)))
+            })
+        }
+        .map_err(|err| ApiError::from_webauthn_error(err, "register"))?;
+
+        // registering always creates a new user, a taken nick or email is a conflict
+        let registered_user = db_manager.create_user(user)?;
+
+        self.creds
+            .lock()
+            .await
+            .entry(username)
+            .or_insert_with(BTreeMap::new)
+            .insert(cred.cred_id.clone(), cred);
 
-        log::info!("completed Register -> {:?}", r);
         log::info!("completed Register for user {:?}", registered_user);
 
         return Ok(registered_user.id);
@@ -118,25 +116,84 @@ impl WebauthnActor {
 
     pub async fn challenge_authenticate(
         &self,
-        nick: &String,
+        nick: &str,
     ) -> WebauthnResult<RequestChallengeResponse> {
+        let nick = &normalize_nick(nick);
         log::info!("handle ChallengeAuthenticate -> {:?}", nick);
 
         // TODO: get the creds from the database here
 
-        let creds = match self.creds.lock().await.get(&nick.as_bytes().to_vec()) {
+        let creds = match self.creds.lock().await.get(&credential_key(nick)) {
             Some(creds) => Some(creds.iter().map(|(_, v)| v.clone()).collect()),
             None => None,
         }
         .ok_or(WebauthnError::CredentialRetrievalError)?;
 
         let (acr, st) = self.wan.generate_challenge_authenticate(creds)?;
-        self.auth_chals
-            .lock()
-            .await
-            .put(nick.as_bytes().to_vec(), st);
+        self.auth_chals.lock().await.put(credential_key(nick), st);
 
         log::debug!("complete ChallengeAuthenticate -> {:?}", acr);
         Ok(acr)
     }
+
+    /// check the authenticator's assertion against the login challenge issued for the nick
+    pub async fn authenticate(&self, nick: &str, lgn: &PublicKeyCredential) -> WebauthnResult<()> {
+        let nick = credential_key(nick);
+        log::info!(
+            "handle Authenticate -> {:?}",
+            String::from_utf8_lossy(&nick)
+        );
+
+        let st = self
+            .auth_chals
+            .lock()
+            .await
+            .pop(&nick)
+            .ok_or(WebauthnError::ChallengeNotFound)?;
+
+        // keep the signature counter up to date so cloned authenticators are detected
+        if let Some((cred_id, counter)) = self.wan.authenticate_credential(lgn, st)? {
+            if let Some(cred) = self
+                .creds
+                .lock()
+                .await
+                .get_mut(&nick)
+                .and_then(|ucreds| ucreds.get_mut(&cred_id))
+            {
+                cred.counter = counter;
+            }
+        }
+
+        log::debug!("complete Authenticate");
+        Ok(())
+    }
+
+    /// drop all credentials registered for a nick, e.g. when the account is deleted
+    pub async fn remove_credentials(&self, nick: &str) {
+        log::info!("removing credentials -> {:?}", nick);
+        self.creds.lock().await.remove(&credential_key(nick));
+    }
+
+    /// move the credentials of a user to their new nick
+    pub async fn rename_credentials(&self, old_nick: &str, new_nick: &str) {
+        let mut creds = self.creds.lock().await;
+        if let Some(ucreds) = creds.remove(&credential_key(old_nick)) {
+            creds.insert(credential_key(new_nick), ucreds);
+        }
+    }
+
+    /// list the credentials registered for a nick without their key material
+    pub async fn credentials_metadata(&self, nick: &str) -> Vec<CredentialMetadata> {
+        match self.creds.lock().await.get(&credential_key(nick)) {
+            Some(creds) => creds
+                .values()
+                .map(|cred| CredentialMetadata {
+                    cred_id: Base64UrlSafeData(cred.cred_id.clone()),
+                    counter: cred.counter,
+                    verified: cred.verified,
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
 }
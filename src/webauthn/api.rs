@@ -5,6 +5,8 @@ use webauthn_rs::proto::{
     RegisterPublicKeyCredential, RequestChallengeResponse, UserId, UserVerificationPolicy,
 };
 
+use crate::api::TokenResponse;
+use crate::auth::Auth;
 use crate::db;
 use crate::errors::ApiError;
 use crate::webauthn::actors::*;
@@ -32,19 +34,22 @@ pub async fn register(
     register_data: RegisterData,
     actor: Arc<WebauthnActor>,
     db_manager: db::DBManager,
+    auth: Arc<Auth>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("handling register");
     let response = actor
         .register(register_data.user, register_data.credentials, db_manager)
         .await;
     match response {
-        Ok(user_id) => return respond(Ok(user_id), warp::http::StatusCode::OK),
-        Err(err) => {
-            return respond(
-                Err(ApiError::from_webauthn_error(err, "register")),
-                warp::http::StatusCode::UNAUTHORIZED,
-            )
+        // hand out a token for the user just created, narrower ones can be derived from it
+        Ok(user_id) => {
+            let result = auth
+                .issue_token(user_id, auth.scopes_for(user_id), None)
+                .map(|token| TokenResponse { user_id, token });
+            return respond(result, warp::http::StatusCode::OK);
         }
+        // conflicts on nick or email keep their own status
+        Err(err) => return respond(Err(err), warp::http::StatusCode::UNAUTHORIZED),
     }
 }
 
@@ -66,6 +71,37 @@ pub async fn challenge_login(
     }
 }
 
+pub async fn login(
+    nick: String,
+    credential: PublicKeyCredential,
+    actor: Arc<WebauthnActor>,
+    db_manager: db::DBManager,
+    auth: Arc<Auth>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("handling login");
+
+    if let Err(err) = actor.authenticate(&nick, &credential).await {
+        return respond(
+            Err(ApiError::from_webauthn_error(err, "login")),
+            warp::http::StatusCode::UNAUTHORIZED,
+        );
+    }
+
+    // signing in during the deletion grace period keeps the account, only once the assertion is verified
+    let result = db_manager
+        .cancel_user_deletion(&nick)
+        .and_then(|_| db_manager.get_user_by_nick(&nick))
+        .and_then(|user| {
+            auth.issue_token(user.id, auth.scopes_for(user.id), None)
+                .map(|token| TokenResponse {
+                    user_id: user.id,
+                    token,
+                })
+        });
+
+    return respond(result, warp::http::StatusCode::OK);
+}
+
 fn respond<T: Serialize>(
     result: Result<T, ApiError>,
     status: warp::http::StatusCode,
@@ -1,8 +1,9 @@
 use serde_derive::{Deserialize, Serialize};
 use std::sync::Arc;
 use warp::Filter;
-use webauthn_rs::proto::RegisterPublicKeyCredential;
+use webauthn_rs::proto::{PublicKeyCredential, RegisterPublicKeyCredential};
 
+use crate::auth::{self, Auth};
 use crate::models::CreateUser;
 use crate::webauthn;
 use crate::webauthn::actors::*;
@@ -35,12 +36,14 @@ pub fn challenge_register(
 pub fn register(
     pool: PgPool,
     actor: Arc<WebauthnActor>,
+    auth: Arc<Auth>,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("register")
         .and(warp::post()) // Match POST method
         .and(with_json_body::<RegisterData>()) // Try to deserialize JSON
         .and(with_webauthn_actor(actor)) // Add the actor
         .and(crate::with_db_access_manager(pool)) // Add the db Manager
+        .and(auth::with_auth(auth)) // Add the token issuer
         .and_then(webauthn::api::register) // Use api method to handle it
 }
 
@@ -53,3 +56,18 @@ pub fn challenge_login(
         .and(with_webauthn_actor(actor)) // Add the actor
         .and_then(webauthn::api::challenge_login) // Use api method to handle it
 }
+
+/// POST /auth/login/nick
+pub fn login(
+    pool: PgPool,
+    actor: Arc<WebauthnActor>,
+    auth: Arc<Auth>,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("login" / String) // Match nick
+        .and(warp::post()) // Match POST method
+        .and(with_json_body::<PublicKeyCredential>()) // Try to deserialize the assertion
+        .and(with_webauthn_actor(actor)) // Add the actor
+        .and(crate::with_db_access_manager(pool)) // Add the db Manager
+        .and(auth::with_auth(auth)) // Add the token issuer
+        .and_then(webauthn::api::login) // Use api method to handle it
+}